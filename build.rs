@@ -0,0 +1,681 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "gl-functions")]
+    functions::generate();
+}
+
+/// Generates the typed function table used by the `gl-functions` feature: the constants and
+/// types (via gl_generator), a table recording which GL version and profile, or which
+/// extensions, provide each command, a typed method on `GlFunctions` for each command, and an
+/// error-checking trampoline for each command.
+#[cfg(feature = "gl-functions")]
+mod functions {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::fs::File;
+    use std::io::{self, BufWriter, Write};
+    use std::path::Path;
+
+    use gl_generator::{generators, Api, Fallbacks, Profile, Registry};
+    use xml::reader::{EventReader, XmlEvent};
+
+    type Version = (u8, u8);
+
+    struct Block {
+        remove: bool,
+        profile: Option<String>,
+        commands: Vec<String>,
+    }
+
+    struct Feature {
+        version: Version,
+        blocks: Vec<Block>,
+    }
+
+    #[derive(Default)]
+    struct Spec {
+        features: Vec<Feature>,
+        extensions: BTreeMap<String, Vec<String>>,
+        /// The `len` attribute of each pointer parameter, by command and parameter name.
+        lens: BTreeMap<String, BTreeMap<String, String>>,
+    }
+
+    fn parse_spec() -> Spec {
+        let mut spec = Spec::default();
+
+        let mut feature: Option<Feature> = None;
+        let mut extension: Option<(String, Vec<String>)> = None;
+        let mut block: Option<Block> = None;
+        // The command being defined in <commands>, its parameter lens, the len of the current
+        // <param> and the text of the current <name>.
+        let mut definition: Option<(String, BTreeMap<String, String>)> = None;
+        let mut param: Option<Option<String>> = None;
+        let mut text: Option<String> = None;
+
+        for event in EventReader::new(khronos_api::GL_XML) {
+            match event.unwrap() {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    let attr = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|a| a.name.local_name == key)
+                            .map(|a| a.value.clone())
+                    };
+
+                    match name.local_name.as_str() {
+                        "feature" if attr("api").as_deref() == Some("gl") => {
+                            let number = attr("number").unwrap();
+                            let (major, minor) = number.split_once('.').unwrap();
+                            feature = Some(Feature {
+                                version: (major.parse().unwrap(), minor.parse().unwrap()),
+                                blocks: Vec::new(),
+                            });
+                        }
+                        "extension" => {
+                            let supported = attr("supported").unwrap_or_default();
+                            if supported.split('|').any(|api| api == "gl") {
+                                extension = Some((attr("name").unwrap(), Vec::new()));
+                            }
+                        }
+                        "require" | "remove" => {
+                            let api = attr("api");
+                            if api.is_none() || api.as_deref() == Some("gl") {
+                                block = Some(Block {
+                                    remove: name.local_name == "remove",
+                                    profile: attr("profile"),
+                                    commands: Vec::new(),
+                                });
+                            }
+                        }
+                        "command" => {
+                            if let (Some(block), Some(name)) = (&mut block, attr("name")) {
+                                block.commands.push(name);
+                            } else if block.is_none() && attr("name").is_none() {
+                                definition = Some((String::new(), BTreeMap::new()));
+                            }
+                        }
+                        "param" if definition.is_some() => param = Some(attr("len")),
+                        "name" if definition.is_some() => text = Some(String::new()),
+                        _ => {}
+                    }
+                }
+                XmlEvent::Characters(characters) => {
+                    if let Some(text) = &mut text {
+                        text.push_str(&characters);
+                    }
+                }
+                XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                    "name" => {
+                        if let (Some((command, lens)), Some(text)) = (&mut definition, text.take())
+                        {
+                            match &param {
+                                Some(Some(len)) => {
+                                    lens.insert(text, len.clone());
+                                }
+                                Some(None) => {}
+                                None => *command = text,
+                            }
+                        }
+                    }
+                    "param" => param = None,
+                    "command" => {
+                        if let Some((command, lens)) = definition.take() {
+                            spec.lens.insert(command, lens);
+                        }
+                    }
+                    "feature" => {
+                        if let Some(feature) = feature.take() {
+                            spec.features.push(feature);
+                        }
+                    }
+                    "extension" => {
+                        if let Some((name, commands)) = extension.take() {
+                            spec.extensions.insert(name, commands);
+                        }
+                    }
+                    "require" | "remove" => {
+                        if let Some(block) = block.take() {
+                            if let Some(feature) = &mut feature {
+                                feature.blocks.push(block);
+                            } else if let Some((_, commands)) = &mut extension {
+                                if !block.remove {
+                                    commands.extend(block.commands);
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        spec.features.sort_by_key(|feature| feature.version);
+        spec
+    }
+
+    /// For each command, the earliest version from which it is provided in every later version
+    /// of `profile`.
+    fn versions(spec: &Spec, profile: &str) -> BTreeMap<String, Version> {
+        let mut present = BTreeSet::new();
+        let mut snapshots = Vec::new();
+
+        for feature in &spec.features {
+            for block in &feature.blocks {
+                if block.profile.as_deref().is_some_and(|p| p != profile) {
+                    continue;
+                }
+                for command in &block.commands {
+                    if block.remove {
+                        present.remove(command);
+                    } else {
+                        present.insert(command.clone());
+                    }
+                }
+            }
+            snapshots.push((feature.version, present.clone()));
+        }
+
+        let mut versions = BTreeMap::new();
+        let mut still_present: Option<BTreeSet<String>> = None;
+        for (version, snapshot) in snapshots.iter().rev() {
+            let current = match &still_present {
+                Some(later) => snapshot.intersection(later).cloned().collect(),
+                None => snapshot.clone(),
+            };
+            for command in &current {
+                versions.insert(command.clone(), *version);
+            }
+            still_present = Some(current);
+        }
+
+        versions
+    }
+
+    const KEYWORDS: &[&str] = &[
+        "as", "box", "break", "const", "continue", "crate", "else", "enum", "extern", "false",
+        "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "priv",
+        "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true", "type",
+        "unsafe", "use", "where", "while",
+    ];
+
+    fn ident(name: &str) -> String {
+        if KEYWORDS.contains(&name) {
+            format!("{}_", name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// The type of a parameter or return value. gl.xml misspells `GLuint` in one array
+    /// parameter, which gl_generator passes through unqualified.
+    fn ty(ty: &str) -> String {
+        ty.replace("[Gluint;", "[types::GLuint;")
+    }
+
+    /// The `types::Name` identifiers referenced in `ty`.
+    fn referenced_types(ty: &str) -> impl Iterator<Item = &str> {
+        ty.split("types::").skip(1).map(|rest| {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            &rest[..end]
+        })
+    }
+
+    /// The size of the elements a pointer parameter of type `ty` points to, if they are plain
+    /// data.
+    fn element_size(ty: &str) -> Option<&'static str> {
+        let ty = ty
+            .trim_start_matches("types::")
+            .trim_start_matches("__gl_imports::raw::");
+        let size = match ty {
+            "GLbyte" | "GLubyte" | "GLboolean" | "GLchar" | "GLcharARB" | "c_void" => "1",
+            "GLshort" | "GLushort" | "GLhalf" | "GLhalfARB" | "GLhalfNV" => "2",
+            "GLint" | "GLuint" | "GLenum" | "GLfloat" | "GLsizei" | "GLbitfield" | "GLfixed"
+            | "GLclampf" => "4",
+            "GLdouble" | "GLclampd" | "GLint64" | "GLuint64" | "GLint64EXT" | "GLuint64EXT" => "8",
+            "GLintptr" | "GLsizeiptr" | "GLintptrARB" | "GLsizeiptrARB" => {
+                "std::mem::size_of::<usize>()"
+            }
+            _ => return None,
+        };
+
+        Some(size)
+    }
+
+    /// Whether the command `name`, without the `gl` prefix, transfers pixels. Its pointers are
+    /// then offsets into the bound pixel buffer, if there is one.
+    fn transfers_pixels(name: &str) -> bool {
+        const PIXEL_COMMANDS: &[&str] = &[
+            "TexImage",
+            "TexSubImage",
+            "TextureImage",
+            "TextureSubImage",
+            "Pixels",
+            "Bitmap",
+            "PolygonStipple",
+            "ColorTable",
+            "ConvolutionFilter",
+            "SeparableFilter",
+            "Histogram",
+            "Minmax",
+        ];
+        PIXEL_COMMANDS.iter().any(|command| name.contains(command))
+    }
+
+    /// Translates a gl.xml `len` attribute, e.g. `count*4` or
+    /// `COMPSIZE(format,type,width,height)`, into a `capture::Len`. `pixels` is whether the
+    /// command transfers pixels.
+    fn len(len: Option<&String>, params: &[&str], pixels: bool) -> String {
+        let index = |name: &str| params.iter().position(|param| *param == name);
+        let len = match len {
+            Some(len) => len.as_str(),
+            None => return "L::Unknown".to_string(),
+        };
+
+        if let Ok(n) = len.parse::<usize>() {
+            return format!("L::Const({})", n);
+        }
+        if let Some(i) = index(len) {
+            if pixels {
+                return format!("L::Pixels({})", i);
+            }
+            return format!("L::Param({}, 1)", i);
+        }
+        if let Some((name, factor)) = len.split_once('*') {
+            if let (Some(i), Ok(factor)) = (index(name), factor.parse::<usize>()) {
+                return format!("L::Param({}, {})", i, factor);
+            }
+        }
+        if let Some(args) = len
+            .strip_prefix("COMPSIZE(")
+            .and_then(|l| l.strip_suffix(')'))
+        {
+            let args: Vec<&str> = args.split(',').collect();
+            let find = |name| {
+                args.iter()
+                    .find(|&&arg| arg == name)
+                    .and_then(|&arg| index(arg))
+            };
+            let optional = |i: Option<usize>| match i {
+                Some(i) => format!("Some({})", i),
+                None => "None".to_string(),
+            };
+            if let (Some(format), Some(ty), Some(width)) =
+                (find("format"), find("type"), find("width"))
+            {
+                return format!(
+                    "L::Image({}, {}, {}, {}, {})",
+                    format,
+                    ty,
+                    width,
+                    optional(find("height")),
+                    optional(find("depth"))
+                );
+            }
+            if let [name] = args[..] {
+                if let Some(i) = index(name) {
+                    return format!("L::Enum({})", i);
+                }
+            }
+            if let [count, ty] = args[..] {
+                if let (Some(count), Some(ty), "count", "type") =
+                    (index(count), index(ty), count, ty)
+                {
+                    return format!("L::Elements({}, {})", count, ty);
+                }
+            }
+        }
+
+        "L::Unknown".to_string()
+    }
+
+    /// The name of a parameter in gl.xml, which gl_generator suffixes with `_` if it's a
+    /// keyword.
+    fn xml_name(ident: &str) -> &str {
+        match ident {
+            "in_" | "ref_" | "type_" => &ident[..ident.len() - 1],
+            _ => ident,
+        }
+    }
+
+    /// How a trace records each parameter of `cmd`, as a `capture::Pointer`.
+    fn pointers(cmd: &gl_generator::Cmd, lens: Option<&BTreeMap<String, String>>) -> Vec<String> {
+        let params: Vec<&str> = cmd
+            .params
+            .iter()
+            .map(|param| xml_name(&param.ident))
+            .collect();
+        let pixels = transfers_pixels(&cmd.proto.ident);
+        let param_len = |param: &str| len(lens.and_then(|lens| lens.get(param)), &params, pixels);
+
+        cmd.params
+            .iter()
+            .map(|param| {
+                let param_ty = ty(&param.ty);
+                let (input, pointee) = if let Some(pointee) = param_ty.strip_prefix("*const ") {
+                    (true, pointee)
+                } else if let Some(pointee) = param_ty.strip_prefix("*mut ") {
+                    (false, pointee)
+                } else {
+                    return "P::Value".to_string();
+                };
+
+                let has_len = lens.is_some_and(|lens| lens.contains_key(xml_name(&param.ident)));
+                if input && pointee.starts_with("*const types::GLchar") {
+                    let lengths = cmd
+                        .params
+                        .iter()
+                        .position(|p| p.ident == "length" && ty(&p.ty) == "*const types::GLint");
+                    let lengths = match lengths {
+                        Some(i) => format!("Some({})", i),
+                        None => "None".to_string(),
+                    };
+                    return format!(
+                        "P::Strings({}, {})",
+                        param_len(xml_name(&param.ident)),
+                        lengths
+                    );
+                }
+                if input && !has_len && pointee.starts_with("types::GLchar") {
+                    return "P::CString".to_string();
+                }
+
+                match element_size(pointee) {
+                    Some(size) if input => {
+                        format!("P::In({}, {})", size, param_len(xml_name(&param.ident)))
+                    }
+                    Some(size) => {
+                        format!("P::Out({}, {})", size, param_len(xml_name(&param.ident)))
+                    }
+                    None if input => "P::Value".to_string(),
+                    None => format!(
+                        "P::Out(std::mem::size_of::<usize>(), {})",
+                        param_len(xml_name(&param.ident))
+                    ),
+                }
+            })
+            .collect()
+    }
+
+    fn write_bindings(registry: &Registry, dest: &mut impl Write) -> io::Result<()> {
+        writeln!(dest, "mod __gl_imports {{ pub use std::os::raw; }}")?;
+
+        // gl.xml references a few types that gl_generator's templates don't define. They are
+        // all pointers, so they are declared as such.
+        let mut types = Vec::new();
+        generators::gen_types(Api::Gl, &mut types)?;
+        let types = String::from_utf8(types).unwrap();
+        let mut missing = BTreeSet::new();
+        for cmd in &registry.cmds {
+            let tys = std::iter::once(&cmd.proto.ty).chain(cmd.params.iter().map(|p| &p.ty));
+            for ty in tys.flat_map(|ty| referenced_types(ty)) {
+                let defined = ["type", "struct", "enum"]
+                    .iter()
+                    .any(|item| types.contains(&format!("{} {} ", item, ty)));
+                if !defined {
+                    missing.insert(ty.to_string());
+                }
+            }
+        }
+        writeln!(dest, "pub mod types {{")?;
+        writeln!(dest, "{}", types)?;
+        for ty in missing {
+            writeln!(
+                dest,
+                "pub type {} = *const super::__gl_imports::raw::c_void;",
+                ty
+            )?;
+        }
+        writeln!(dest, "}}")?;
+
+        // Some extensions define the same enum name with different values for GL and GLES.
+        // Those are left out rather than guessed.
+        let mut values: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for enm in &registry.enums {
+            values.entry(&enm.ident).or_default().insert(&enm.value);
+        }
+        let mut written = BTreeSet::new();
+        for enm in &registry.enums {
+            if values[enm.ident.as_str()].len() != 1 || !written.insert(&enm.ident) {
+                continue;
+            }
+            // A few NV enums are negative, which gl_generator writes as a negated GLenum.
+            if enm.ty == "GLenum" && enm.value.starts_with('-') {
+                writeln!(
+                    dest,
+                    "pub const {}: types::GLenum = ({}i32) as types::GLenum;",
+                    enm.ident, enm.value
+                )?;
+            } else {
+                generators::gen_enum_item(enm, "types::", dest)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn generate() {
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        let spec = parse_spec();
+
+        let extension_names: Vec<&str> = spec.extensions.keys().map(String::as_str).collect();
+        let registry = Registry::new(
+            Api::Gl,
+            (4, 6),
+            Profile::Compatibility,
+            Fallbacks::None,
+            &extension_names,
+        );
+
+        let mut bindings =
+            BufWriter::new(File::create(Path::new(&out_dir).join("gl_bindings.rs")).unwrap());
+        write_bindings(&registry, &mut bindings).unwrap();
+
+        let core = versions(&spec, "core");
+        let compatibility = versions(&spec, "compatibility");
+
+        let mut providers: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+        for (extension, commands) in &spec.extensions {
+            for command in commands {
+                let providers = providers.entry(command.clone()).or_default();
+                if !providers.contains(&extension.as_str()) {
+                    providers.push(extension);
+                }
+            }
+        }
+
+        let format_version = |version: Option<&Version>| match version {
+            Some((major, minor)) => format!("Some(({}, {}))", major, minor),
+            None => "None".to_string(),
+        };
+
+        let mut commands =
+            BufWriter::new(File::create(Path::new(&out_dir).join("gl_commands.rs")).unwrap());
+
+        writeln!(commands, "mod __gl_imports {{ pub use std::os::raw; }}").unwrap();
+        writeln!(commands, "pub(crate) static COMMANDS: &[Command] = &[").unwrap();
+        for cmd in &registry.cmds {
+            let name = format!("gl{}", cmd.proto.ident);
+            let extensions = providers.get(&name).cloned().unwrap_or_default();
+            writeln!(
+                commands,
+                "    Command {{ name: {:?}, core: {}, compatibility: {}, extensions: &{:?} }},",
+                name,
+                format_version(core.get(&name)),
+                format_version(compatibility.get(&name)),
+                extensions,
+            )
+            .unwrap();
+        }
+        writeln!(commands, "];").unwrap();
+
+        writeln!(commands, "impl GlFunctions {{").unwrap();
+        for (index, cmd) in registry.cmds.iter().enumerate() {
+            let params: Vec<String> = cmd
+                .params
+                .iter()
+                .map(|param| format!("{}: {}", ident(&param.ident), ty(&param.ty)))
+                .collect();
+            let types: Vec<String> = cmd.params.iter().map(|param| ty(&param.ty)).collect();
+            let args: Vec<String> = cmd.params.iter().map(|param| ident(&param.ident)).collect();
+            writeln!(
+                commands,
+                "    /// Calls `gl{name}`.\n    ///\n    /// # Panics\n    ///\n    \
+                     /// If the function isn't available to the context, which \
+                     [`GlFunctions::get`] reports as an error instead.\n    \
+                     #[inline] pub unsafe fn {name}(&self, {params}) -> {ret} {{ \
+                     std::mem::transmute::<*const c_void, extern \"system\" fn({types}) -> {ret}>\
+                     (self.address({index}))({args}) }}",
+                name = cmd.proto.ident,
+                params = params.join(", "),
+                types = types.join(", "),
+                ret = ty(&cmd.proto.ty),
+                index = index,
+                args = args.join(", "),
+            )
+            .unwrap();
+        }
+        writeln!(commands, "}}").unwrap();
+
+        // Each trampoline calls the current context's address for the command, then checks
+        // glGetError. The arguments are only formatted if there was an error.
+        for (index, cmd) in registry.cmds.iter().enumerate() {
+            let params: Vec<String> = cmd
+                .params
+                .iter()
+                .map(|param| format!("{}: {}", ident(&param.ident), ty(&param.ty)))
+                .collect();
+            let types: Vec<String> = cmd.params.iter().map(|param| ty(&param.ty)).collect();
+            let args: Vec<String> = cmd.params.iter().map(|param| ident(&param.ident)).collect();
+            let format = vec!["{:?}"; args.len()].join(", ");
+            writeln!(
+                commands,
+                "extern \"system\" fn checked_{name}({params}) -> {ret} {{ unsafe {{ \
+                     let ret = std::mem::transmute::<*const c_void, \
+                     extern \"system\" fn({types}) -> {ret}>(checked_address({index}))({args}); \
+                     check_errors({index}, &|| format!(\"{format}\"{comma}{args})); ret }} }}",
+                name = cmd.proto.ident,
+                params = params.join(", "),
+                types = types.join(", "),
+                ret = ty(&cmd.proto.ty),
+                index = index,
+                args = args.join(", "),
+                format = format,
+                comma = if args.is_empty() { "" } else { ", " },
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            commands,
+            "pub(crate) fn trampoline(index: usize) -> *const c_void {{ match index {{"
+        )
+        .unwrap();
+        for (index, cmd) in registry.cmds.iter().enumerate() {
+            writeln!(
+                commands,
+                "    {} => checked_{} as *const c_void,",
+                index, cmd.proto.ident
+            )
+            .unwrap();
+        }
+        writeln!(commands, "    _ => unreachable!(), }} }}").unwrap();
+
+        write_tracing(&registry, &spec, &mut commands).unwrap();
+    }
+
+    /// Writes the recording trampolines, the parameter descriptions they record by, and the
+    /// functions that replay a recorded call, all behind the `gl-trace` feature.
+    fn write_tracing(registry: &Registry, spec: &Spec, dest: &mut impl Write) -> io::Result<()> {
+        writeln!(
+            dest,
+            "#[cfg(feature = \"gl-trace\")] use crate::capture::{{self, Len as L, Pointer as P}};"
+        )?;
+
+        writeln!(dest, "#[cfg(feature = \"gl-trace\")]")?;
+        writeln!(dest, "pub(crate) static PARAMS: &[&[P]] = &[")?;
+        for cmd in &registry.cmds {
+            let name = format!("gl{}", cmd.proto.ident);
+            let pointers = pointers(cmd, spec.lens.get(&name));
+            writeln!(dest, "    &[{}],", pointers.join(", "))?;
+        }
+        writeln!(dest, "];")?;
+
+        for (index, cmd) in registry.cmds.iter().enumerate() {
+            let params: Vec<String> = cmd
+                .params
+                .iter()
+                .map(|param| format!("{}: {}", ident(&param.ident), ty(&param.ty)))
+                .collect();
+            let types: Vec<String> = cmd.params.iter().map(|param| ty(&param.ty)).collect();
+            let args: Vec<String> = cmd.params.iter().map(|param| ident(&param.ident)).collect();
+            let arg_bits: Vec<String> = args
+                .iter()
+                .map(|arg| format!("capture::bits({})", arg))
+                .collect();
+            let replay_args: Vec<String> = (0..args.len())
+                .map(|i| format!("capture::from_bits(args[{}])", i))
+                .collect();
+            let signature = format!(
+                "extern \"system\" fn({}) -> {}",
+                types.join(", "),
+                ty(&cmd.proto.ty)
+            );
+
+            writeln!(
+                dest,
+                "#[cfg(feature = \"gl-trace\")] extern \"system\" fn traced_{name}({params}) -> {ret} {{ unsafe {{ \
+                     let ret = std::mem::transmute::<*const c_void, {signature}>(capture::traced_address({index}))({args}); \
+                     capture::record_call({index}, &[{arg_bits}], capture::bits(ret)); ret }} }}",
+                name = cmd.proto.ident,
+                params = params.join(", "),
+                ret = ty(&cmd.proto.ty),
+                signature = signature,
+                index = index,
+                args = args.join(", "),
+                arg_bits = arg_bits.join(", "),
+            )?;
+            writeln!(
+                dest,
+                "#[cfg(feature = \"gl-trace\")] unsafe fn replay_{name}(addr: *const c_void, {args_param}: &[u64]) -> u64 {{ \
+                     capture::bits(std::mem::transmute::<*const c_void, {signature}>(addr)({replay_args})) }}",
+                name = cmd.proto.ident,
+                args_param = if args.is_empty() { "_args" } else { "args" },
+                signature = signature,
+                replay_args = replay_args.join(", "),
+            )?;
+        }
+
+        writeln!(dest, "#[cfg(feature = \"gl-trace\")]")?;
+        writeln!(
+            dest,
+            "pub(crate) fn traced_trampoline(index: usize) -> *const c_void {{ match index {{"
+        )?;
+        for (index, cmd) in registry.cmds.iter().enumerate() {
+            writeln!(
+                dest,
+                "    {} => traced_{} as *const c_void,",
+                index, cmd.proto.ident
+            )?;
+        }
+        writeln!(dest, "    _ => unreachable!(), }} }}")?;
+
+        // `args` holds one value per parameter, with pointers already pointing at replay data.
+        writeln!(dest, "#[cfg(feature = \"gl-trace\")]")?;
+        writeln!(dest, "pub(crate) unsafe fn replay_call(index: usize, addr: *const c_void, args: &[u64]) -> u64 {{ match index {{")?;
+        for (index, cmd) in registry.cmds.iter().enumerate() {
+            writeln!(
+                dest,
+                "    {} => replay_{}(addr, args),",
+                index, cmd.proto.ident
+            )?;
+        }
+        writeln!(dest, "    _ => unreachable!(), }} }}")?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! The context of whichever backend created it: the platform's, or with the `mock` feature the
+//! mock backend, chosen per context with [`GlConfig::backend`].
+//!
+//! [`GlConfig::backend`]: crate::GlConfig::backend
+
+use std::ffi::{c_void, CStr};
+
+use raw_window_handle::RawWindowHandle;
+
+#[cfg(feature = "mock")]
+use crate::mock;
+use crate::platform;
+use crate::{Backend, GlConfig, GlError, GlxInfo, Ownership, RawGlHandles};
+
+/// What was current on the thread before [`Context::make_current_saving`].
+pub(crate) enum Previous {
+    Platform(platform::Previous),
+    #[cfg(feature = "mock")]
+    Mock(mock::Previous),
+}
+
+pub(crate) enum Context {
+    Platform(platform::GlContext),
+    #[cfg(feature = "mock")]
+    Mock(mock::GlContext),
+}
+
+/// Calls `$method` on the backend's context.
+macro_rules! dispatch {
+    ($self:ident.$method:ident($($arg:expr),*)) => {
+        match $self {
+            Context::Platform(context) => context.$method($($arg),*),
+            #[cfg(feature = "mock")]
+            Context::Mock(context) => context.$method($($arg),*),
+        }
+    };
+}
+
+impl Context {
+    pub unsafe fn create(handle: RawWindowHandle, config: GlConfig) -> Result<Context, GlError> {
+        match config.backend {
+            #[cfg(feature = "mock")]
+            Some(Backend::Mock) => Ok(Context::Mock(mock::GlContext::create(handle, config)?)),
+            _ => Ok(Context::Platform(platform::GlContext::create(
+                handle, config,
+            )?)),
+        }
+    }
+
+    pub unsafe fn from_raw(
+        handles: RawGlHandles,
+        ownership: Ownership,
+    ) -> Result<Context, GlError> {
+        match handles {
+            #[cfg(feature = "mock")]
+            RawGlHandles::Mock { .. } => Ok(Context::Mock(mock::GlContext::from_raw(
+                handles, ownership,
+            )?)),
+            _ => Ok(Context::Platform(platform::GlContext::from_raw(
+                handles, ownership,
+            )?)),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self {
+            Context::Platform(_) => platform::BACKEND,
+            #[cfg(feature = "mock")]
+            Context::Mock(_) => mock::BACKEND,
+        }
+    }
+
+    pub unsafe fn make_current(&self) {
+        dispatch!(self.make_current())
+    }
+
+    /// Makes the context current, returning what was current on the thread before, for
+    /// [`Context::restore`].
+    pub unsafe fn make_current_saving(&self) -> Previous {
+        match self {
+            Context::Platform(context) => Previous::Platform(context.make_current_saving()),
+            #[cfg(feature = "mock")]
+            Context::Mock(context) => Previous::Mock(context.make_current_saving()),
+        }
+    }
+
+    pub unsafe fn restore(&self, previous: Previous) {
+        match (self, previous) {
+            (Context::Platform(context), Previous::Platform(previous)) => context.restore(previous),
+            #[cfg(feature = "mock")]
+            (Context::Mock(context), Previous::Mock(previous)) => context.restore(previous),
+            #[cfg(feature = "mock")]
+            _ => unreachable!(),
+        }
+    }
+
+    pub unsafe fn make_not_current(&self) {
+        dispatch!(self.make_not_current())
+    }
+
+    pub fn raw_handles(&self) -> RawGlHandles {
+        dispatch!(self.raw_handles())
+    }
+
+    pub fn is_current(&self) -> bool {
+        dispatch!(self.is_current())
+    }
+
+    pub fn get_proc_address_cstr(&self, symbol: &CStr) -> *const c_void {
+        dispatch!(self.get_proc_address_cstr(symbol))
+    }
+
+    pub fn swap_buffers(&self) {
+        dispatch!(self.swap_buffers())
+    }
+
+    pub fn actual_config(&self) -> &GlConfig {
+        dispatch!(self.actual_config())
+    }
+
+    pub fn is_direct(&self) -> bool {
+        dispatch!(self.is_direct())
+    }
+
+    pub fn platform_extensions(&self) -> &[String] {
+        dispatch!(self.platform_extensions())
+    }
+
+    pub fn glx_info(&self) -> Option<GlxInfo> {
+        dispatch!(self.glx_info())
+    }
+}
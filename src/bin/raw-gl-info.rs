@@ -0,0 +1,312 @@
+//! Prints what this crate can create on the current display, like `glxinfo`: the renderer, the
+//! platform and GL extensions, the versions and profiles that can be created, and every
+//! framebuffer configuration.
+//!
+//! ```text
+//! cargo run --features info-tool --bin raw-gl-info -- [--json]
+//! ```
+//!
+//! The `RAW_GL_*` variables read by `GlConfig::with_env_overrides` apply to the default
+//! context, so a failing configuration can be reproduced.
+
+#[cfg(target_os = "linux")]
+fn main() {
+    let mut json = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("usage: raw-gl-info [--json]");
+                return;
+            }
+            _ => {
+                eprintln!("unknown argument {:?}; usage: raw-gl-info [--json]", arg);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let report = match unsafe { glx::report() } {
+        Some(report) => report,
+        None => {
+            eprintln!("raw-gl-info: can't open the X display; is DISPLAY set?");
+            std::process::exit(1);
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        report.print();
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("raw-gl-info only supports GLX so far");
+    std::process::exit(1);
+}
+
+#[cfg(target_os = "linux")]
+mod glx {
+    use std::os::raw::c_ulong;
+
+    use raw_gl_context::glx::enumerate_configs;
+    use raw_gl_context::mesa::Renderer;
+    use raw_gl_context::{Backend, GlConfig, GlContext, Profile, RendererInfo};
+    use raw_window_handle::unix::XlibHandle;
+    use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+    use serde::Serialize;
+    use x11::xlib;
+
+    const VERSIONS: &[(u8, u8)] = &[
+        (2, 0),
+        (2, 1),
+        (3, 0),
+        (3, 1),
+        (3, 2),
+        (3, 3),
+        (4, 0),
+        (4, 1),
+        (4, 2),
+        (4, 3),
+        (4, 4),
+        (4, 5),
+        (4, 6),
+    ];
+
+    #[derive(Serialize)]
+    pub struct Report {
+        backend: Backend,
+        display: String,
+        screen: i32,
+        /// GLX_MESA_query_renderer, if supported.
+        mesa_renderer: Option<Renderer>,
+        default_context: Result<ContextReport, String>,
+        attempts: Vec<Attempt>,
+        configs: Vec<GlConfig>,
+    }
+
+    #[derive(Serialize)]
+    struct ContextReport {
+        requested: GlConfig,
+        actual: GlConfig,
+        info: RendererInfo,
+        platform_extensions: Vec<String>,
+        gl_extensions: Vec<String>,
+    }
+
+    #[derive(Serialize)]
+    struct Attempt {
+        version: (u8, u8),
+        profile: Profile,
+        /// The version and profile actually created, or the error.
+        result: Result<((u8, u8), Profile), String>,
+    }
+
+    struct Window {
+        display: *mut xlib::Display,
+        window: c_ulong,
+    }
+
+    unsafe impl HasRawWindowHandle for Window {
+        fn raw_window_handle(&self) -> RawWindowHandle {
+            RawWindowHandle::Xlib(XlibHandle {
+                window: self.window,
+                display: self.display as *mut _,
+                ..XlibHandle::empty()
+            })
+        }
+    }
+
+    pub unsafe fn report() -> Option<Report> {
+        let display = xlib::XOpenDisplay(std::ptr::null());
+        if display.is_null() {
+            return None;
+        }
+        let screen = xlib::XDefaultScreen(display);
+        let name = std::ffi::CStr::from_ptr(xlib::XDisplayString(display))
+            .to_string_lossy()
+            .into_owned();
+
+        let root = xlib::XRootWindow(display, screen);
+        let window = Window {
+            display,
+            window: xlib::XCreateSimpleWindow(display, root, 0, 0, 64, 64, 0, 0, 0),
+        };
+
+        let default_context = GlConfig::default()
+            .with_env_overrides()
+            .and_then(|requested| {
+                let context = GlContext::create(&window, requested.clone())?;
+                Ok(ContextReport {
+                    requested,
+                    actual: context.actual_config().clone(),
+                    info: context.info().clone(),
+                    platform_extensions: context.platform_extensions().to_vec(),
+                    gl_extensions: context.gl_extensions().to_vec(),
+                })
+            })
+            .map_err(|err| format!("{:?}", err));
+
+        let mut attempts = Vec::new();
+        for &version in VERSIONS {
+            for &profile in &[Profile::Compatibility, Profile::Core] {
+                if profile == Profile::Core && version < (3, 2) {
+                    continue;
+                }
+
+                // Only the version and profile vary, so that framebuffer requirements don't
+                // mask version failures.
+                let mut config = GlConfig::default();
+                config.version = version;
+                config.profile = profile;
+                config.srgb = false;
+                let result = GlContext::create(&window, config)
+                    .map(|context| {
+                        let actual = context.actual_config();
+                        (actual.version, actual.profile)
+                    })
+                    .map_err(|err| format!("{:?}", err));
+
+                attempts.push(Attempt {
+                    version,
+                    profile,
+                    result,
+                });
+            }
+        }
+
+        let report = Report {
+            backend: Backend::Glx,
+            display: name,
+            screen,
+            mesa_renderer: Renderer::query(display as *mut _, screen),
+            default_context,
+            attempts,
+            configs: enumerate_configs(display as *mut _, screen),
+        };
+
+        xlib::XDestroyWindow(display, window.window);
+        xlib::XCloseDisplay(display);
+
+        Some(report)
+    }
+
+    impl Report {
+        pub fn print(&self) {
+            println!("backend: {:?}", self.backend);
+            println!("display: {}, screen {}", self.display, self.screen);
+
+            match &self.default_context {
+                Ok(context) => {
+                    let info = &context.info;
+                    if let Some(glx) = &info.glx {
+                        println!("GLX server: {} {}", glx.server_vendor, glx.server_version);
+                        println!("GLX client: {} {}", glx.client_vendor, glx.client_version);
+                    }
+                    println!("direct rendering: {}", yes_no(info.direct));
+                    println!("vendor: {}", info.vendor);
+                    println!("renderer: {}", info.renderer);
+                    println!("software: {}", yes_no(info.software));
+                    println!("version: {}", info.version);
+                    println!(
+                        "shading language version: {}",
+                        info.shading_language_version
+                    );
+                    println!("requested config: {:?}", context.requested);
+                    println!("actual config: {:?}", context.actual);
+                }
+                Err(err) => println!("default context: failed: {}", err),
+            }
+
+            if let Some(renderer) = &self.mesa_renderer {
+                println!();
+                println!("GLX_MESA_query_renderer:");
+                println!(
+                    "    device: {} {} ({:04x}:{:04x})",
+                    renderer.vendor, renderer.device, renderer.vendor_id, renderer.device_id
+                );
+                println!("    driver version: {:?}", renderer.driver_version);
+                println!("    accelerated: {}", yes_no(renderer.accelerated));
+                println!("    video memory: {} MB", renderer.video_memory);
+                println!("    unified memory: {}", yes_no(renderer.unified_memory));
+                println!("    preferred profile: {:?}", renderer.preferred_profile);
+                println!(
+                    "    max core version: {}",
+                    version(renderer.max_core_version)
+                );
+                println!(
+                    "    max compatibility version: {}",
+                    version(renderer.max_compatibility_version)
+                );
+                println!("    max ES version: {}", version(renderer.max_es_version));
+            }
+
+            if let Ok(context) = &self.default_context {
+                print_list("platform extensions", &context.platform_extensions);
+                print_list("GL extensions", &context.gl_extensions);
+            }
+
+            println!();
+            println!("versions:");
+            for attempt in &self.attempts {
+                let result = match &attempt.result {
+                    Ok((actual, profile)) => {
+                        format!("ok, got {} {:?}", version(Some(*actual)), profile)
+                    }
+                    Err(err) => format!("failed: {}", err),
+                };
+                println!(
+                    "    {} {:?}: {}",
+                    version(Some(attempt.version)),
+                    attempt.profile,
+                    result
+                );
+            }
+
+            println!();
+            println!("configs ({}):", self.configs.len());
+            println!("      r  g  b  a depth stencil samples srgb double stereo format");
+            for config in &self.configs {
+                println!(
+                    "    {:>3}{:>3}{:>3}{:>3}{:>6}{:>8}{:>8}{:>5}{:>7}{:>7} {:?}",
+                    config.red_bits,
+                    config.green_bits,
+                    config.blue_bits,
+                    config.alpha_bits,
+                    config.depth_bits,
+                    config.stencil_bits,
+                    config.samples.unwrap_or(0),
+                    yes_no(config.srgb),
+                    yes_no(config.double_buffer),
+                    yes_no(config.stereo),
+                    config.color_format,
+                );
+            }
+        }
+    }
+
+    fn print_list(title: &str, items: &[String]) {
+        println!();
+        println!("{} ({}):", title, items.len());
+        for item in items {
+            println!("    {}", item);
+        }
+    }
+
+    fn version(version: Option<(u8, u8)>) -> String {
+        match version {
+            Some((major, minor)) => format!("{}.{}", major, minor),
+            None => "none".to_string(),
+        }
+    }
+
+    fn yes_no(value: bool) -> &'static str {
+        if value {
+            "yes"
+        } else {
+            "no"
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Replays a trace recorded with `GlConfig::record_trace` on an X display, starting Xvfb if
+//! there isn't one, and writes the back buffer to `frame_NNNN.png` at every `swap_buffers`.
+//!
+//! ```text
+//! cargo run --features gl-trace,testing --bin raw-gl-replay -- trace.rglt [--out DIR] [--size WxH]
+//! ```
+//!
+//! The window is the size of the recorded viewport unless `--size` is given.
+
+const USAGE: &str = "usage: raw-gl-replay TRACE [--out DIR] [--size WxH]";
+
+/// Used when the trace doesn't record a viewport, e.g. because glGetIntegerv wasn't available.
+const DEFAULT_SIZE: (u32, u32) = (640, 480);
+
+#[cfg(target_os = "linux")]
+fn main() {
+    let mut trace = None;
+    let mut out = std::path::PathBuf::from(".");
+    let mut size = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out = args.next().unwrap_or_else(|| usage()).into(),
+            "--size" => {
+                let value = args.next().unwrap_or_else(|| usage());
+                size = Some(parse_size(&value).unwrap_or_else(|| usage()));
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if trace.is_none() && !arg.starts_with('-') => trace = Some(arg),
+            _ => usage(),
+        }
+    }
+    let trace = trace.unwrap_or_else(|| usage());
+
+    if let Err(err) = replay(&trace, &out, size) {
+        eprintln!("raw-gl-replay: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("raw-gl-replay only supports GLX so far");
+    std::process::exit(1);
+}
+
+#[cfg(target_os = "linux")]
+fn replay(
+    path: &str,
+    out: &std::path::Path,
+    size: Option<(u32, u32)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use raw_gl_context::replay::Trace;
+    use raw_gl_context::testing::{Display, GoldenTest, TestWindow};
+    use raw_gl_context::{GlContext, PixelFormat, Region};
+
+    let trace = Trace::open(path)?;
+    let (width, height) = match size.unwrap_or_else(|| trace.viewport()) {
+        (0, _) | (_, 0) => DEFAULT_SIZE,
+        size => size,
+    };
+
+    std::fs::create_dir_all(out)?;
+    let display = Display::open().map_err(|err| format!("{:?}", err))?;
+    let window = TestWindow::new(&display, width, height).map_err(|err| format!("{:?}", err))?;
+    let mut config = GoldenTest::default_config();
+    config.version = trace.version();
+    config.profile = trace.profile();
+
+    unsafe {
+        let context = GlContext::create(&window, config).map_err(|err| format!("{:?}", err))?;
+        context.make_current();
+
+        let mut result = Ok(());
+        trace.replay(&context, |frame| {
+            if result.is_ok() {
+                let path = out.join(format!("frame_{:04}.png", frame));
+                result = context
+                    .read_pixels(Region::full(width, height), PixelFormat::Rgba8)
+                    .map_err(|err| format!("{:?}", err))
+                    .and_then(|image| image.save_png(path).map_err(|err| err.to_string()));
+            }
+            context.swap_buffers();
+        })?;
+        context.make_not_current();
+        result?;
+    }
+
+    println!("replayed {} frames", trace.frames());
+
+    Ok(())
+}
+
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
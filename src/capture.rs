@@ -0,0 +1,911 @@
+//! Recording of GL calls for contexts created with [`GlConfig::record_trace`].
+//! `get_proc_address` returns a trampoline for each GL function, which calls the function
+//! through the context current on the thread and then appends the call to the trace file,
+//! along with the data its pointer arguments point to. See [`crate::replay`] for the format.
+//!
+//! [`GlConfig::record_trace`]: crate::GlConfig::record_trace
+
+use std::cell::{Cell, RefCell};
+use std::ffi::{c_void, CStr};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::functions::{
+    abort, find_command, gl, traced_trampoline, FallbackAddresses, COMMANDS, PARAMS,
+};
+use crate::replay::{
+    write_varint, ARG_BLOB, ARG_SCRATCH, ARG_STRINGS, ARG_UNSIZED, ARG_VALUE, FORMAT_VERSION,
+    MAGIC, RECORD_CALL, RECORD_DEFINE, RECORD_SWAP,
+};
+use crate::Profile;
+
+type GetIntegerv = extern "system" fn(u32, *mut i32);
+
+/// Outputs whose size depends on an enum, like glGetIntegerv's, get a scratch buffer this
+/// large on replay.
+const ENUM_OUT_LEN: usize = 4096;
+
+/// How a parameter is recorded. Generated for each command from the `len` attributes in
+/// gl.xml.
+pub(crate) enum Pointer {
+    /// Not a pointer, or a pointer recorded as an address, such as a buffer offset.
+    Value,
+    /// Data read by the call: the element size and count.
+    In(usize, Len),
+    /// Data written by the call, replaced by a scratch buffer on replay. Calls are skipped on
+    /// replay if the size of the data can't be worked out.
+    Out(usize, Len),
+    /// A NUL-terminated string.
+    CString,
+    /// An array of strings, with their lengths in parameter `.1` if they aren't
+    /// NUL-terminated.
+    Strings(Len, Option<usize>),
+}
+
+/// The number of elements behind a pointer parameter.
+pub(crate) enum Len {
+    Unknown,
+    /// Depends on the enum parameter `.0`, such as a `pname`.
+    Enum(usize),
+    Const(usize),
+    /// Parameter `.0` times `.1`.
+    Param(usize, usize),
+    /// Parameter `.0` bytes of pixels, such as a compressed image, or an offset into the bound
+    /// pixel buffer.
+    Pixels(usize),
+    /// The pixels of an image, from the format, type, width, height and depth parameters.
+    Image(usize, usize, usize, Option<usize>, Option<usize>),
+    /// Indices, from the count and type parameters.
+    Elements(usize, usize),
+}
+
+/// The bits of a parameter or return value, which is never larger than 8 bytes.
+pub(crate) fn bits<T: Copy>(value: T) -> u64 {
+    let mut bits = 0u64;
+    let size = std::mem::size_of::<T>().min(8);
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            &value as *const T as *const u8,
+            &mut bits as *mut u64 as *mut u8,
+            size,
+        );
+    }
+    bits
+}
+
+pub(crate) unsafe fn from_bits<T: Copy>(bits: u64) -> T {
+    std::ptr::read_unaligned(&bits as *const u64 as *const T)
+}
+
+pub(crate) struct Recorder {
+    file: RefCell<BufWriter<File>>,
+    /// The context's own address for each command, filled in as they are looked up.
+    addresses: Box<[Cell<*const c_void>]>,
+    /// Whether each command's name has been written to the trace.
+    defined: Box<[Cell<bool>]>,
+    get_integerv: Option<GetIntegerv>,
+    version: (u8, u8),
+    profile: Profile,
+    /// The header is written the first time the context is made current, since it includes
+    /// the viewport.
+    started: Cell<bool>,
+    /// Set after a write fails, which stops recording.
+    failed: Cell<bool>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<Recorder>>> = const { RefCell::new(None) };
+}
+
+static FALLBACK: FallbackAddresses = FallbackAddresses::new();
+
+impl Recorder {
+    pub fn create(
+        path: &Path,
+        get_integerv: *const c_void,
+        version: (u8, u8),
+        profile: Profile,
+    ) -> io::Result<Recorder> {
+        Ok(Recorder {
+            file: RefCell::new(BufWriter::new(File::create(path)?)),
+            addresses: (0..COMMANDS.len())
+                .map(|_| Cell::new(std::ptr::null()))
+                .collect(),
+            defined: (0..COMMANDS.len()).map(|_| Cell::new(false)).collect(),
+            get_integerv: if get_integerv.is_null() {
+                None
+            } else {
+                Some(unsafe { std::mem::transmute::<*const c_void, GetIntegerv>(get_integerv) })
+            },
+            version,
+            profile,
+            started: Cell::new(false),
+            failed: Cell::new(false),
+        })
+    }
+
+    /// Returns the trampoline for `symbol`, whose address in this context is `addr`. Unknown
+    /// functions are returned unwrapped, and aren't recorded.
+    pub fn wrap(&self, symbol: &str, addr: *const c_void) -> *const c_void {
+        if addr.is_null() {
+            return addr;
+        }
+
+        match find_command(symbol) {
+            Some(index) => {
+                self.addresses[index].set(addr);
+                FALLBACK.set(index, addr);
+                traced_trampoline(index)
+            }
+            None => addr,
+        }
+    }
+
+    pub fn swap(&self) {
+        // Swapping without ever making the context current is pointless, but the trace still
+        // needs a header.
+        self.start();
+        self.write(|file| {
+            file.write_all(&[RECORD_SWAP])?;
+            file.flush()
+        });
+    }
+
+    pub fn flush(&self) {
+        self.write(|file| file.flush());
+    }
+
+    fn get_integer(&self, name: u32) -> i32 {
+        let mut value = [0; 4];
+        if let Some(get_integerv) = self.get_integerv {
+            get_integerv(name, value.as_mut_ptr());
+        }
+        value[0]
+    }
+
+    /// Whether a buffer is bound to `binding`, in which case pointers are offsets into it.
+    fn is_bound(&self, binding: u32) -> bool {
+        self.get_integer(binding) != 0
+    }
+
+    fn start(&self) {
+        if self.started.replace(true) {
+            return;
+        }
+
+        let mut viewport = [0; 4];
+        if let Some(get_integerv) = self.get_integerv {
+            get_integerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        }
+        self.write(|file| {
+            file.write_all(MAGIC)?;
+            file.write_all(&FORMAT_VERSION.to_le_bytes())?;
+            file.write_all(&[
+                self.version.0,
+                self.version.1,
+                (self.profile == Profile::Core) as u8,
+            ])?;
+            file.write_all(&(viewport[2].max(0) as u32).to_le_bytes())?;
+            file.write_all(&(viewport[3].max(0) as u32).to_le_bytes())
+        });
+    }
+
+    fn write(&self, f: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) {
+        if self.failed.get() {
+            return;
+        }
+
+        if let Err(_err) = f(&mut self.file.borrow_mut()) {
+            warn!(error = %_err, "writing the GL trace failed; recording stopped");
+            self.failed.set(true);
+        }
+    }
+
+    fn record(&self, index: usize, args: &[u64], ret: u64) {
+        // Reading what pointers point to can require GL queries, so it happens before the
+        // file is borrowed.
+        let args: Vec<Arg> = PARAMS[index]
+            .iter()
+            .zip(args)
+            .map(|(pointer, &value)| unsafe { self.arg(pointer, value, args) })
+            .collect();
+
+        self.write(|file| {
+            if !self.defined[index].replace(true) {
+                let name = COMMANDS[index].name;
+                file.write_all(&[RECORD_DEFINE])?;
+                write_varint(file, index as u64)?;
+                write_varint(file, name.len() as u64)?;
+                file.write_all(name.as_bytes())?;
+            }
+
+            file.write_all(&[RECORD_CALL])?;
+            write_varint(file, index as u64)?;
+            write_varint(file, args.len() as u64)?;
+            for arg in &args {
+                arg.write(file)?;
+            }
+            write_varint(file, ret)
+        });
+    }
+
+    /// Reads the argument `value`, one of `args`, as described by `pointer`.
+    unsafe fn arg(&self, pointer: &Pointer, value: u64, args: &[u64]) -> Arg {
+        let addr = value as usize as *const u8;
+
+        if addr.is_null() {
+            return Arg::Value(value);
+        }
+
+        match pointer {
+            Pointer::Value => Arg::Value(value),
+            Pointer::CString => Arg::Blob(
+                CStr::from_ptr(addr as *const _)
+                    .to_bytes_with_nul()
+                    .to_vec(),
+            ),
+            Pointer::In(size, len) => match self.size(*size, len, args, false) {
+                Some(size) => Arg::Blob(std::slice::from_raw_parts(addr, size).to_vec()),
+                None => Arg::Value(value),
+            },
+            Pointer::Out(size, len) => match (len, self.size(*size, len, args, true)) {
+                (Len::Enum(_), _) => Arg::Scratch(ENUM_OUT_LEN),
+                (_, Some(size)) => Arg::Scratch(size),
+                // Not a pointer but an offset into a pixel buffer.
+                (Len::Image(..) | Len::Pixels(_), None) => Arg::Value(value),
+                (_, None) => Arg::Unsized,
+            },
+            Pointer::Strings(count, lengths) => {
+                let count = match self.size(1, count, args, false) {
+                    Some(count) => count,
+                    None => return Arg::Value(value),
+                };
+                let strings = std::slice::from_raw_parts(addr as *const *const u8, count);
+                let lengths = lengths
+                    .map(|i| args[i] as usize as *const i32)
+                    .filter(|lengths| !lengths.is_null());
+                let strings = strings
+                    .iter()
+                    .enumerate()
+                    .map(
+                        |(i, &string)| match lengths.map(|lengths| *lengths.add(i)) {
+                            Some(len) if len >= 0 => {
+                                std::slice::from_raw_parts(string, len as usize).to_vec()
+                            }
+                            _ => CStr::from_ptr(string as *const _).to_bytes().to_vec(),
+                        },
+                    )
+                    .collect();
+                Arg::Strings(strings)
+            }
+        }
+    }
+
+    /// The size in bytes of the data behind a pointer to elements of `size` bytes, or `None`
+    /// if it can't be worked out or the pointer is an offset into a bound buffer. `pack` is
+    /// whether the call writes pixels rather than reading them.
+    fn size(&self, size: usize, len: &Len, args: &[u64], pack: bool) -> Option<usize> {
+        match *len {
+            Len::Unknown => None,
+            Len::Enum(i) => Some(size * enum_len(args[i] as u32)),
+            Len::Const(n) => Some(size * n),
+            Len::Param(i, factor) => Some(size * count(args[i]) * factor),
+            Len::Pixels(i) => {
+                if self.pixel_buffer_bound(pack) {
+                    return None;
+                }
+                Some(size * count(args[i]))
+            }
+            Len::Image(format, ty, width, height, depth) => {
+                if self.pixel_buffer_bound(pack) {
+                    return None;
+                }
+                let pixel = pixel_size(args[format] as u32, args[ty] as u32)?;
+                let store = self.pixel_store(pack, depth.is_some());
+                Some(store.image_size(
+                    pixel,
+                    count(args[width]),
+                    height.map(|i| count(args[i])),
+                    depth.map(|i| count(args[i])),
+                ))
+            }
+            Len::Elements(n, ty) => {
+                // Element array buffers are core since 1.5.
+                if self.version >= (1, 5) && self.is_bound(gl::ELEMENT_ARRAY_BUFFER_BINDING) {
+                    return None;
+                }
+                let size = match args[ty] as u32 {
+                    gl::UNSIGNED_BYTE => 1,
+                    gl::UNSIGNED_SHORT => 2,
+                    gl::UNSIGNED_INT => 4,
+                    _ => return None,
+                };
+                Some(count(args[n]) * size)
+            }
+        }
+    }
+
+    /// Whether a pixel pack or unpack buffer is bound, making pixel pointers offsets into it.
+    fn pixel_buffer_bound(&self, pack: bool) -> bool {
+        // Pixel buffer objects are core since 2.1.
+        if self.version < (2, 1) {
+            return false;
+        }
+        let binding = if pack {
+            gl::PIXEL_PACK_BUFFER_BINDING
+        } else {
+            gl::PIXEL_UNPACK_BUFFER_BINDING
+        };
+        self.is_bound(binding)
+    }
+
+    /// The current pack or unpack parameters. Those for 3D images are only queried for them,
+    /// since they don't exist before GL 1.2.
+    fn pixel_store(&self, pack: bool, three_d: bool) -> PixelStore {
+        let names = if pack {
+            [
+                gl::PACK_ALIGNMENT,
+                gl::PACK_ROW_LENGTH,
+                gl::PACK_SKIP_PIXELS,
+                gl::PACK_SKIP_ROWS,
+                gl::PACK_IMAGE_HEIGHT,
+                gl::PACK_SKIP_IMAGES,
+            ]
+        } else {
+            [
+                gl::UNPACK_ALIGNMENT,
+                gl::UNPACK_ROW_LENGTH,
+                gl::UNPACK_SKIP_PIXELS,
+                gl::UNPACK_SKIP_ROWS,
+                gl::UNPACK_IMAGE_HEIGHT,
+                gl::UNPACK_SKIP_IMAGES,
+            ]
+        };
+        if self.get_integerv.is_none() {
+            return PixelStore::DEFAULT;
+        }
+
+        let get = |name| self.get_integer(name).max(0) as usize;
+        PixelStore {
+            alignment: get(names[0]),
+            row_length: get(names[1]),
+            skip_pixels: get(names[2]),
+            skip_rows: get(names[3]),
+            image_height: if three_d { get(names[4]) } else { 0 },
+            skip_images: if three_d { get(names[5]) } else { 0 },
+        }
+    }
+}
+
+/// The `GL_PACK_*` or `GL_UNPACK_*` parameters that decide where the pixels of an image are
+/// in memory.
+#[derive(Clone, Copy, Debug)]
+struct PixelStore {
+    alignment: usize,
+    row_length: usize,
+    skip_pixels: usize,
+    skip_rows: usize,
+    image_height: usize,
+    skip_images: usize,
+}
+
+impl PixelStore {
+    const DEFAULT: PixelStore = PixelStore {
+        alignment: 4,
+        row_length: 0,
+        skip_pixels: 0,
+        skip_rows: 0,
+        image_height: 0,
+        skip_images: 0,
+    };
+
+    /// The number of bytes from the start of an image to the end of its last pixel, as laid out
+    /// in section 8.4.4.1 of the GL 4.6 spec. Rows are padded to the alignment, except the
+    /// last one, which GL doesn't read past.
+    fn image_size(
+        &self,
+        pixel: usize,
+        width: usize,
+        height: Option<usize>,
+        depth: Option<usize>,
+    ) -> usize {
+        let rows = height.unwrap_or(1);
+        let images = depth.unwrap_or(1);
+        if width == 0 || rows == 0 || images == 0 {
+            return 0;
+        }
+
+        let row_length = if self.row_length > 0 {
+            self.row_length
+        } else {
+            width
+        };
+        let alignment = self.alignment.max(1);
+        let row_stride = (row_length * pixel).div_ceil(alignment) * alignment;
+        let image_height = if self.image_height > 0 {
+            self.image_height
+        } else {
+            rows
+        };
+        let image_stride = row_stride * image_height;
+
+        // Skipped rows and images only apply to images with that many dimensions.
+        let skip_rows = if height.is_some() { self.skip_rows } else { 0 };
+        let skip_images = if depth.is_some() { self.skip_images } else { 0 };
+
+        (skip_images + images - 1) * image_stride
+            + (skip_rows + rows - 1) * row_stride
+            + (self.skip_pixels + width) * pixel
+    }
+}
+
+enum Arg {
+    Value(u64),
+    Blob(Vec<u8>),
+    Scratch(usize),
+    Strings(Vec<Vec<u8>>),
+    Unsized,
+}
+
+impl Arg {
+    fn write(&self, file: &mut impl Write) -> io::Result<()> {
+        match self {
+            Arg::Value(value) => {
+                file.write_all(&[ARG_VALUE])?;
+                write_varint(file, *value)
+            }
+            Arg::Blob(bytes) => {
+                file.write_all(&[ARG_BLOB])?;
+                write_varint(file, bytes.len() as u64)?;
+                file.write_all(bytes)
+            }
+            Arg::Scratch(len) => {
+                file.write_all(&[ARG_SCRATCH])?;
+                write_varint(file, *len as u64)
+            }
+            Arg::Strings(strings) => {
+                file.write_all(&[ARG_STRINGS])?;
+                write_varint(file, strings.len() as u64)?;
+                for string in strings {
+                    write_varint(file, string.len() as u64)?;
+                    file.write_all(string)?;
+                }
+                Ok(())
+            }
+            Arg::Unsized => file.write_all(&[ARG_UNSIZED]),
+        }
+    }
+}
+
+/// A count parameter, which may be a signed 32-bit `GLsizei` or a pointer-sized
+/// `GLsizeiptr`. Negative counts are errors that GL rejects, and read nothing.
+fn count(bits: u64) -> usize {
+    let count = if bits >> 32 == 0 {
+        bits as u32 as i32 as i64
+    } else {
+        bits as i64
+    };
+    count.max(0) as usize
+}
+
+/// The number of values a parameter-setting function like glTexParameteriv, glFogfv or
+/// glLightfv reads for `pname`. Most take one; those that take more are listed, and anything
+/// else is read as one value, so that a single value is never read past.
+fn enum_len(pname: u32) -> usize {
+    match pname {
+        gl::TEXTURE_BORDER_COLOR
+        | gl::TEXTURE_SWIZZLE_RGBA
+        | gl::FOG_COLOR
+        | gl::LIGHT_MODEL_AMBIENT
+        | gl::AMBIENT
+        | gl::DIFFUSE
+        | gl::SPECULAR
+        | gl::POSITION
+        | gl::EMISSION
+        | gl::AMBIENT_AND_DIFFUSE
+        | gl::TEXTURE_ENV_COLOR
+        | gl::OBJECT_PLANE
+        | gl::EYE_PLANE
+        | gl::PATCH_DEFAULT_OUTER_LEVEL => 4,
+        gl::SPOT_DIRECTION | gl::COLOR_INDEXES | gl::POINT_DISTANCE_ATTENUATION => 3,
+        gl::PATCH_DEFAULT_INNER_LEVEL => 2,
+        _ => 1,
+    }
+}
+
+/// The size of a pixel in the given format and type.
+fn pixel_size(format: u32, ty: u32) -> Option<usize> {
+    let components = match format {
+        gl::RED
+        | gl::GREEN
+        | gl::BLUE
+        | gl::ALPHA
+        | gl::LUMINANCE
+        | gl::COLOR_INDEX
+        | gl::DEPTH_COMPONENT
+        | gl::STENCIL_INDEX
+        | gl::RED_INTEGER
+        | gl::GREEN_INTEGER
+        | gl::BLUE_INTEGER => 1,
+        gl::RG | gl::RG_INTEGER | gl::LUMINANCE_ALPHA | gl::DEPTH_STENCIL => 2,
+        gl::RGB | gl::BGR | gl::RGB_INTEGER | gl::BGR_INTEGER => 3,
+        gl::RGBA | gl::BGRA | gl::RGBA_INTEGER | gl::BGRA_INTEGER => 4,
+        _ => return None,
+    };
+
+    let size = match ty {
+        gl::BYTE | gl::UNSIGNED_BYTE => components,
+        gl::SHORT | gl::UNSIGNED_SHORT | gl::HALF_FLOAT => 2 * components,
+        gl::INT | gl::UNSIGNED_INT | gl::FLOAT => 4 * components,
+        gl::UNSIGNED_BYTE_3_3_2 | gl::UNSIGNED_BYTE_2_3_3_REV => 1,
+        gl::UNSIGNED_SHORT_5_6_5
+        | gl::UNSIGNED_SHORT_5_6_5_REV
+        | gl::UNSIGNED_SHORT_4_4_4_4
+        | gl::UNSIGNED_SHORT_4_4_4_4_REV
+        | gl::UNSIGNED_SHORT_5_5_5_1
+        | gl::UNSIGNED_SHORT_1_5_5_5_REV => 2,
+        gl::UNSIGNED_INT_8_8_8_8
+        | gl::UNSIGNED_INT_8_8_8_8_REV
+        | gl::UNSIGNED_INT_10_10_10_2
+        | gl::UNSIGNED_INT_2_10_10_10_REV
+        | gl::UNSIGNED_INT_24_8
+        | gl::UNSIGNED_INT_10F_11F_11F_REV
+        | gl::UNSIGNED_INT_5_9_9_9_REV => 4,
+        gl::FLOAT_32_UNSIGNED_INT_24_8_REV => 8,
+        _ => return None,
+    };
+
+    Some(size)
+}
+
+/// Makes `recorder` the one used by trampolines on this thread, writing the trace header the
+/// first time.
+pub(crate) fn make_current(recorder: Option<&Rc<Recorder>>) {
+    if let Some(recorder) = recorder {
+        recorder.start();
+    }
+
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        if recorder.is_some() || current.is_some() {
+            *current = recorder.cloned();
+        }
+    });
+}
+
+/// Stops trampolines on this thread from using `recorder`, if it is current. Called when its
+/// context is destroyed.
+pub(crate) fn release(recorder: &Rc<Recorder>) {
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        if current
+            .as_ref()
+            .is_some_and(|current| Rc::ptr_eq(current, recorder))
+        {
+            *current = None;
+        }
+    });
+}
+
+/// The address of command `index` in the current context, or the last one looked up by any
+/// recording context if the current one didn't look it up. Called by the trampolines.
+pub(crate) fn traced_address(index: usize) -> *const c_void {
+    let addr = CURRENT.with(|current| match &*current.borrow() {
+        Some(recorder) => recorder.addresses[index].get(),
+        None => std::ptr::null(),
+    });
+    if !addr.is_null() {
+        return addr;
+    }
+
+    let addr = FALLBACK.get(index);
+    if addr.is_null() {
+        abort(format_args!(
+            "{} was called through a recording trampoline, but no context has looked it up",
+            COMMANDS[index].name
+        ));
+    }
+
+    addr
+}
+
+/// Appends a call of command `index` to the current trace. Called by the trampolines after
+/// the call, with its return value. Calls made through another context's address aren't
+/// recorded.
+pub(crate) fn record_call(index: usize, args: &[u64], ret: u64) {
+    let recorder = CURRENT.with(|current| current.borrow().clone());
+    if let Some(recorder) = recorder.filter(|recorder| !recorder.addresses[index].get().is_null()) {
+        let result =
+            std::panic::catch_unwind(AssertUnwindSafe(|| recorder.record(index, args, ret)));
+        if result.is_err() {
+            abort(format_args!("recording {} panicked", COMMANDS[index].name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::ops::Deref;
+    use std::path::PathBuf;
+
+    thread_local! {
+        static INTEGERS: RefCell<HashMap<u32, i32>> = RefCell::new(HashMap::new());
+    }
+
+    extern "system" fn get_integerv(name: u32, value: *mut i32) {
+        let integer = INTEGERS.with(|integers| integers.borrow().get(&name).copied());
+        unsafe { *value = integer.unwrap_or(0) };
+    }
+
+    /// A recorder writing to a file named after `test`, which is removed on drop.
+    struct TestRecorder {
+        recorder: Recorder,
+        path: PathBuf,
+    }
+
+    impl Deref for TestRecorder {
+        type Target = Recorder;
+
+        fn deref(&self) -> &Recorder {
+            &self.recorder
+        }
+    }
+
+    impl Drop for TestRecorder {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    fn recorder(test: &str, integers: &[(u32, i32)]) -> TestRecorder {
+        INTEGERS.with(|map| *map.borrow_mut() = integers.iter().copied().collect());
+        let path =
+            std::env::temp_dir().join(format!("raw-gl-{}-{}.rglt", test, std::process::id()));
+        let recorder =
+            Recorder::create(&path, get_integerv as *const c_void, (3, 3), Profile::Core).unwrap();
+        TestRecorder { recorder, path }
+    }
+
+    const RGB: u64 = gl::RGB as u64;
+    const UNSIGNED_BYTE: u64 = gl::UNSIGNED_BYTE as u64;
+
+    /// glTexImage2D's format, type, width and height.
+    fn image_2d() -> Len {
+        Len::Image(0, 1, 2, Some(3), None)
+    }
+
+    #[test]
+    fn image_without_padding_after_last_row() {
+        let recorder = recorder(
+            "image_without_padding_after_last_row",
+            &[(gl::UNPACK_ALIGNMENT, 4)],
+        );
+        let args = [RGB, UNSIGNED_BYTE, 1, 1];
+        assert_eq!(recorder.size(1, &image_2d(), &args, false), Some(3));
+    }
+
+    #[test]
+    fn image_odd_width() {
+        let recorder = recorder("image_odd_width", &[(gl::UNPACK_ALIGNMENT, 1)]);
+        let args = [RGB, UNSIGNED_BYTE, 3, 2];
+        assert_eq!(recorder.size(1, &image_2d(), &args, false), Some(18));
+
+        let recorder = self::recorder("image_odd_width_aligned", &[(gl::UNPACK_ALIGNMENT, 4)]);
+        assert_eq!(recorder.size(1, &image_2d(), &args, false), Some(21));
+    }
+
+    #[test]
+    fn image_row_length_and_skips() {
+        let recorder = recorder(
+            "image_row_length_and_skips",
+            &[
+                (gl::UNPACK_ALIGNMENT, 1),
+                (gl::UNPACK_ROW_LENGTH, 8),
+                (gl::UNPACK_SKIP_PIXELS, 2),
+                (gl::UNPACK_SKIP_ROWS, 1),
+            ],
+        );
+        let args = [RGB, UNSIGNED_BYTE, 3, 2];
+        // Two skipped rows of 8 pixels, 2 skipped pixels, then 3 pixels.
+        assert_eq!(
+            recorder.size(1, &image_2d(), &args, false),
+            Some(2 * 24 + 5 * 3)
+        );
+    }
+
+    #[test]
+    fn image_3d_uses_image_height() {
+        let recorder = recorder(
+            "image_3d_uses_image_height",
+            &[
+                (gl::UNPACK_ALIGNMENT, 1),
+                (gl::UNPACK_IMAGE_HEIGHT, 4),
+                (gl::UNPACK_SKIP_IMAGES, 1),
+            ],
+        );
+        let args = [RGB, UNSIGNED_BYTE, 2, 2, 2];
+        let len = Len::Image(0, 1, 2, Some(3), Some(4));
+        // Images are 4 rows of 6 bytes apart.
+        assert_eq!(recorder.size(1, &len, &args, false), Some(2 * 24 + 6 + 6));
+    }
+
+    #[test]
+    fn image_uses_pack_state_for_outputs() {
+        let recorder = recorder(
+            "image_uses_pack_state_for_outputs",
+            &[(gl::UNPACK_ALIGNMENT, 8), (gl::PACK_ALIGNMENT, 1)],
+        );
+        let args = [RGB, UNSIGNED_BYTE, 3, 2];
+        assert_eq!(recorder.size(1, &image_2d(), &args, true), Some(18));
+        assert_eq!(recorder.size(1, &image_2d(), &args, false), Some(16 + 9));
+    }
+
+    #[test]
+    fn image_in_pixel_buffer() {
+        let recorder = recorder(
+            "image_in_pixel_buffer",
+            &[(gl::PIXEL_UNPACK_BUFFER_BINDING, 1)],
+        );
+        let args = [RGB, UNSIGNED_BYTE, 3, 2];
+        assert_eq!(recorder.size(1, &image_2d(), &args, false), None);
+        assert!(recorder.size(1, &image_2d(), &args, true).is_some());
+    }
+
+    #[test]
+    fn compressed_image_in_pixel_buffer() {
+        // glCompressedTexImage2D's imageSize and data.
+        let len = Len::Pixels(6);
+        let data = Pointer::In(1, Len::Pixels(6));
+        let args = [0, 0, 0, 4, 4, 0, 8, 16];
+
+        let recorder = recorder("compressed_image", &[]);
+        assert_eq!(recorder.size(1, &len, &args, false), Some(8));
+
+        // The data is an offset into the buffer, and must not be read.
+        let recorder = self::recorder(
+            "compressed_image_in_pixel_buffer",
+            &[(gl::PIXEL_UNPACK_BUFFER_BINDING, 1)],
+        );
+        assert_eq!(recorder.size(1, &len, &args, false), None);
+        assert!(matches!(
+            unsafe { recorder.arg(&data, 16, &args) },
+            Arg::Value(16)
+        ));
+    }
+
+    #[test]
+    fn readn_pixels_into_pixel_buffer() {
+        // glReadnPixels's bufSize and data.
+        let len = Len::Pixels(6);
+        let data = Pointer::Out(1, Len::Pixels(6));
+        let args = [0, 0, 2, 2, RGB, UNSIGNED_BYTE, 12, 32];
+
+        let recorder = recorder("readn_pixels", &[]);
+        assert!(matches!(
+            unsafe { recorder.arg(&data, 32, &args) },
+            Arg::Scratch(12)
+        ));
+
+        let recorder = self::recorder(
+            "readn_pixels_into_pixel_buffer",
+            &[(gl::PIXEL_PACK_BUFFER_BINDING, 1)],
+        );
+        assert_eq!(recorder.size(1, &len, &args, true), None);
+        assert!(matches!(
+            unsafe { recorder.arg(&data, 32, &args) },
+            Arg::Value(32)
+        ));
+    }
+
+    #[test]
+    fn elements() {
+        let recorder = recorder("elements", &[]);
+        let len = Len::Elements(0, 1);
+        assert_eq!(
+            recorder.size(1, &len, &[6, gl::UNSIGNED_SHORT as u64], false),
+            Some(12)
+        );
+        assert_eq!(recorder.size(1, &len, &[6, gl::FLOAT as u64], false), None);
+
+        let recorder = self::recorder(
+            "elements_in_buffer",
+            &[(gl::ELEMENT_ARRAY_BUFFER_BINDING, 1)],
+        );
+        assert_eq!(
+            recorder.size(1, &len, &[6, gl::UNSIGNED_SHORT as u64], false),
+            None
+        );
+    }
+
+    #[test]
+    fn enum_sized_inputs() {
+        let recorder = recorder("enum_sized_inputs", &[]);
+        let len = Len::Enum(1);
+        let args = [gl::TEXTURE_2D as u64, gl::TEXTURE_MIN_FILTER as u64];
+        assert_eq!(recorder.size(4, &len, &args, false), Some(4));
+        let args = [gl::TEXTURE_2D as u64, gl::TEXTURE_BORDER_COLOR as u64];
+        assert_eq!(recorder.size(4, &len, &args, false), Some(16));
+        let args = [gl::LIGHT0 as u64, gl::SPOT_DIRECTION as u64];
+        assert_eq!(recorder.size(4, &len, &args, false), Some(12));
+    }
+
+    #[test]
+    fn params_and_constants() {
+        let recorder = recorder("params_and_constants", &[]);
+        assert_eq!(recorder.size(4, &Len::Param(0, 2), &[3], false), Some(24));
+        assert_eq!(recorder.size(4, &Len::Const(16), &[], false), Some(64));
+        assert_eq!(recorder.size(4, &Len::Unknown, &[], false), None);
+    }
+
+    fn params(name: &str) -> &'static [Pointer] {
+        PARAMS[find_command(name).unwrap()]
+    }
+
+    #[test]
+    fn generated_lens() {
+        assert!(matches!(
+            params("glTexImage2D")[8],
+            Pointer::In(1, Len::Image(6, 7, 3, Some(4), None))
+        ));
+        assert!(matches!(
+            params("glReadPixels")[6],
+            Pointer::Out(1, Len::Image(4, 5, 2, Some(3), None))
+        ));
+        assert!(matches!(
+            params("glTexParameteriv")[2],
+            Pointer::In(4, Len::Enum(1))
+        ));
+        assert!(matches!(
+            params("glGetIntegerv")[1],
+            Pointer::Out(4, Len::Enum(0))
+        ));
+        assert!(matches!(
+            params("glDrawElements")[3],
+            Pointer::In(1, Len::Elements(1, 2))
+        ));
+        assert!(matches!(
+            params("glUniform4fv")[2],
+            Pointer::In(4, Len::Param(1, 4))
+        ));
+        assert!(matches!(
+            params("glUniformMatrix4fv")[3],
+            Pointer::In(4, Len::Param(1, 16))
+        ));
+        assert!(matches!(
+            params("glCompressedTexImage2D")[7],
+            Pointer::In(1, Len::Pixels(6))
+        ));
+        assert!(matches!(
+            params("glReadnPixels")[7],
+            Pointer::Out(1, Len::Pixels(6))
+        ));
+        assert!(matches!(
+            params("glGetProgramBinary")[4],
+            Pointer::Out(1, Len::Param(1, 1))
+        ));
+        assert!(matches!(
+            params("glShaderSource")[2],
+            Pointer::Strings(Len::Param(1, 1), Some(3))
+        ));
+    }
+
+    #[test]
+    fn swap_writes_header() {
+        let path = std::env::temp_dir().join(format!("raw-gl-swap-{}.rglt", std::process::id()));
+        let recorder =
+            Recorder::create(&path, std::ptr::null(), (2, 1), Profile::Compatibility).unwrap();
+        recorder.swap();
+        recorder.flush();
+
+        let trace = crate::replay::Trace::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(trace.version(), (2, 1));
+        assert_eq!(trace.frames(), 1);
+    }
+}
@@ -0,0 +1,207 @@
+//! Error checking for contexts created with [`GlConfig::check_errors`]. `get_proc_address`
+//! returns a trampoline for each GL function, which calls the function through the context
+//! current on the thread and then checks `glGetError`.
+//!
+//! [`GlConfig::check_errors`]: crate::GlConfig::check_errors
+
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+
+use crate::functions::{abort, find_command, trampoline, FallbackAddresses, COMMANDS};
+
+type GetError = extern "system" fn() -> u32;
+
+/// glGetError can keep returning `GL_CONTEXT_LOST`, so it is only read this many times after a
+/// call.
+const MAX_ERRORS: usize = 8;
+
+/// A GL error raised by a call made through an error-checking trampoline.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlCallError {
+    pub function: &'static str,
+    /// The arguments of the call, formatted with `Debug`.
+    pub arguments: String,
+    pub error: u32,
+}
+
+impl GlCallError {
+    /// The name of the error, e.g. `"GL_INVALID_ENUM"`.
+    pub fn error_name(&self) -> &'static str {
+        match self.error {
+            0x0500 => "GL_INVALID_ENUM",
+            0x0501 => "GL_INVALID_VALUE",
+            0x0502 => "GL_INVALID_OPERATION",
+            0x0503 => "GL_STACK_OVERFLOW",
+            0x0504 => "GL_STACK_UNDERFLOW",
+            0x0505 => "GL_OUT_OF_MEMORY",
+            0x0506 => "GL_INVALID_FRAMEBUFFER_OPERATION",
+            0x0507 => "GL_CONTEXT_LOST",
+            _ => "unknown error",
+        }
+    }
+}
+
+impl fmt::Display for GlCallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}({}) raised {} ({:#06x})",
+            self.function,
+            self.arguments,
+            self.error_name(),
+            self.error
+        )
+    }
+}
+
+type Callback = Box<dyn Fn(&GlCallError)>;
+
+pub(crate) struct ErrorChecker {
+    get_error: GetError,
+    /// The context's own address for each command, filled in as they are looked up.
+    addresses: Box<[Cell<*const c_void>]>,
+    /// glGetError can't be called between glBegin and glEnd.
+    in_begin_end: Cell<bool>,
+    callback: RefCell<Option<Callback>>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<ErrorChecker>>> = const { RefCell::new(None) };
+}
+
+static FALLBACK: FallbackAddresses = FallbackAddresses::new();
+
+impl ErrorChecker {
+    /// Returns `None` if `get_error` is null.
+    pub fn new(get_error: *const c_void) -> Option<ErrorChecker> {
+        if get_error.is_null() {
+            return None;
+        }
+
+        Some(ErrorChecker {
+            get_error: unsafe { std::mem::transmute::<*const c_void, GetError>(get_error) },
+            addresses: (0..COMMANDS.len())
+                .map(|_| Cell::new(std::ptr::null()))
+                .collect(),
+            in_begin_end: Cell::new(false),
+            callback: RefCell::new(None),
+        })
+    }
+
+    /// Returns the trampoline for `symbol`, whose address in this context is `addr`. Unknown
+    /// functions, and glGetError itself, are returned unwrapped.
+    pub fn wrap(&self, symbol: &str, addr: *const c_void) -> *const c_void {
+        if addr.is_null() || symbol == "glGetError" {
+            return addr;
+        }
+
+        match find_command(symbol) {
+            Some(index) => {
+                self.addresses[index].set(addr);
+                FALLBACK.set(index, addr);
+                trampoline(index)
+            }
+            None => addr,
+        }
+    }
+
+    pub fn set_callback(&self, callback: Option<Callback>) {
+        *self.callback.borrow_mut() = callback;
+    }
+
+    fn check(&self, index: usize, arguments: &dyn Fn() -> String) {
+        let function = COMMANDS[index].name;
+        match function {
+            "glBegin" => {
+                self.in_begin_end.set(true);
+                return;
+            }
+            "glEnd" => self.in_begin_end.set(false),
+            _ if self.in_begin_end.get() => return,
+            _ => {}
+        }
+
+        for _ in 0..MAX_ERRORS {
+            let error = (self.get_error)();
+            if error == 0 {
+                break;
+            }
+
+            let error = GlCallError {
+                function,
+                arguments: arguments(),
+                error,
+            };
+            match &*self.callback.borrow() {
+                Some(callback) => {
+                    let result = std::panic::catch_unwind(AssertUnwindSafe(|| callback(&error)));
+                    if result.is_err() {
+                        abort(format_args!("the GL error callback panicked on {}", error));
+                    }
+                }
+                None => abort(format_args!("{}", error)),
+            }
+        }
+    }
+}
+
+/// Makes `checker` the one used by trampolines on this thread. Threads that never make an
+/// error-checked context current only pay for the thread-local lookup.
+pub(crate) fn make_current(checker: Option<&Rc<ErrorChecker>>) {
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        if checker.is_some() || current.is_some() {
+            *current = checker.cloned();
+        }
+    });
+}
+
+/// Stops trampolines on this thread from using `checker`, if it is current. Called when its
+/// context is destroyed.
+pub(crate) fn release(checker: &Rc<ErrorChecker>) {
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        if current
+            .as_ref()
+            .is_some_and(|current| Rc::ptr_eq(current, checker))
+        {
+            *current = None;
+        }
+    });
+}
+
+/// The address of command `index` in the current context, or the last one looked up by any
+/// error-checked context if the current one didn't look it up. Called by the trampolines.
+pub(crate) fn checked_address(index: usize) -> *const c_void {
+    let addr = CURRENT.with(|current| match &*current.borrow() {
+        Some(checker) => checker.addresses[index].get(),
+        None => std::ptr::null(),
+    });
+    if !addr.is_null() {
+        return addr;
+    }
+
+    let addr = FALLBACK.get(index);
+    if addr.is_null() {
+        abort(format_args!(
+            "{} was called through an error-checking trampoline, but no context has looked it up",
+            COMMANDS[index].name
+        ));
+    }
+
+    addr
+}
+
+/// Reports any errors raised by command `index`. Called by the trampolines. Calls made with a
+/// context current that isn't error-checked, or that didn't look the command up, aren't
+/// checked, since its glGetError may not be the one the checker holds.
+pub(crate) fn check_errors(index: usize, arguments: &dyn Fn() -> String) {
+    // The checker is cloned out so that a callback can make another context current.
+    let checker = CURRENT.with(|current| current.borrow().clone());
+    if let Some(checker) = checker.filter(|checker| !checker.addresses[index].get().is_null()) {
+        checker.check(index, arguments);
+    }
+}
@@ -0,0 +1,705 @@
+use crate::{
+    platform, Backend, ColorFormat, DirectRendering, GlConfig, GlError, Matching, Profile,
+};
+
+use std::path::PathBuf;
+
+const GL_VERSIONS: &[(u8, u8)] = &[
+    (1, 0),
+    (1, 1),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (1, 5),
+    (2, 0),
+    (2, 1),
+    (3, 0),
+    (3, 1),
+    (3, 2),
+    (3, 3),
+    (4, 0),
+    (4, 1),
+    (4, 2),
+    (4, 3),
+    (4, 4),
+    (4, 5),
+    (4, 6),
+];
+
+const MAX_DEPTH_BITS: u8 = 32;
+const MAX_STENCIL_BITS: u8 = 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// Not an OpenGL version that exists, e.g. 3.7.
+    InvalidVersion((u8, u8)),
+    /// A sample count of zero. Use `None` to disable multisampling.
+    InvalidSamples(u8),
+    InvalidDepthBits(u8),
+    InvalidStencilBits(u8),
+    /// An environment variable read by [`GlConfig::with_env_overrides`] could not be parsed.
+    InvalidEnvVar {
+        name: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+    /// [`GlConfig::backend`] or `RAW_GL_BACKEND` named a backend that isn't available on this
+    /// platform.
+    BackendNotAvailable(String),
+    /// The config uses an option that requires a crate feature which isn't enabled.
+    FeatureNotEnabled(&'static str),
+    /// Two options that can't be used together.
+    ConflictingOptions(&'static str, &'static str),
+}
+
+/// An environment variable that was applied by [`GlConfig::with_env_overrides`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvOverride {
+    pub name: &'static str,
+    pub value: String,
+}
+
+impl GlConfig {
+    pub fn builder() -> GlConfigBuilder {
+        GlConfigBuilder {
+            config: GlConfig::default(),
+        }
+    }
+
+    /// Checks the config for values that no driver could satisfy.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !GL_VERSIONS.contains(&self.version) {
+            return Err(ConfigError::InvalidVersion(self.version));
+        }
+
+        if self.samples == Some(0) {
+            return Err(ConfigError::InvalidSamples(0));
+        }
+
+        if self.depth_bits > MAX_DEPTH_BITS {
+            return Err(ConfigError::InvalidDepthBits(self.depth_bits));
+        }
+
+        if self.stencil_bits > MAX_STENCIL_BITS {
+            return Err(ConfigError::InvalidStencilBits(self.stencil_bits));
+        }
+
+        if let Some(backend) = self.backend {
+            if !backend_available(backend) {
+                return Err(ConfigError::BackendNotAvailable(
+                    format!("{:?}", backend).to_ascii_lowercase(),
+                ));
+            }
+        }
+
+        if self.check_errors && !cfg!(feature = "gl-functions") {
+            return Err(ConfigError::FeatureNotEnabled("gl-functions"));
+        }
+
+        if self.record_trace.is_some() {
+            if !cfg!(feature = "gl-trace") {
+                return Err(ConfigError::FeatureNotEnabled("gl-trace"));
+            }
+            if self.check_errors {
+                return Err(ConfigError::ConflictingOptions(
+                    "check_errors",
+                    "record_trace",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies overrides from the environment on top of this config, for debugging without a
+    /// rebuild. The following variables are read:
+    ///
+    /// - `RAW_GL_VERSION`: the context version, e.g. `3.3`.
+    /// - `RAW_GL_PROFILE`: `core` or `compat`.
+    /// - `RAW_GL_SAMPLES`: the number of MSAA samples, or `0` to disable multisampling.
+    /// - `RAW_GL_SRGB`, `RAW_GL_DOUBLE_BUFFER`, `RAW_GL_VSYNC`: `1`/`0`, `true`/`false`,
+    ///   `on`/`off` or `yes`/`no`.
+    /// - `RAW_GL_BACKEND`: `glx`, `wgl`, `cgl` or `mock`. Only the platform's native backend,
+    ///   and the mock backend with the `mock` feature, are accepted.
+    ///
+    /// The applied overrides are recorded in [`GlConfig::env_overrides`], replacing those of
+    /// any earlier call, and reported by [`GlContext::diagnostics`](crate::GlContext::diagnostics).
+    pub fn with_env_overrides(mut self) -> Result<GlConfig, GlError> {
+        self.env_overrides.clear();
+
+        for &name in ENV_VARS {
+            let value = match std::env::var_os(name) {
+                Some(value) => value.to_string_lossy().into_owned(),
+                None => continue,
+            };
+
+            apply_override(&mut self, name, value.trim()).map_err(GlError::InvalidConfig)?;
+
+            self.env_overrides.push(EnvOverride { name, value });
+        }
+
+        self.validate().map_err(GlError::InvalidConfig)?;
+
+        Ok(self)
+    }
+
+    /// The environment variables applied by [`GlConfig::with_env_overrides`].
+    pub fn env_overrides(&self) -> &[EnvOverride] {
+        &self.env_overrides
+    }
+}
+
+const ENV_VARS: &[&str] = &[
+    "RAW_GL_VERSION",
+    "RAW_GL_PROFILE",
+    "RAW_GL_SAMPLES",
+    "RAW_GL_SRGB",
+    "RAW_GL_DOUBLE_BUFFER",
+    "RAW_GL_VSYNC",
+    "RAW_GL_BACKEND",
+];
+
+fn apply_override(
+    config: &mut GlConfig,
+    name: &'static str,
+    value: &str,
+) -> Result<(), ConfigError> {
+    let invalid = |expected| ConfigError::InvalidEnvVar {
+        name,
+        value: value.to_string(),
+        expected,
+    };
+
+    let parse_bool = |value: &str| match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "on" | "yes" => Ok(true),
+        "0" | "false" | "off" | "no" => Ok(false),
+        _ => Err(invalid("1, 0, true, false, on, off, yes or no")),
+    };
+
+    match name {
+        "RAW_GL_VERSION" => {
+            let version = value
+                .split_once('.')
+                .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)));
+            config.version = version.ok_or_else(|| invalid("a version like 3.3"))?;
+        }
+        "RAW_GL_PROFILE" => {
+            config.profile = match value.to_ascii_lowercase().as_str() {
+                "core" => Profile::Core,
+                "compat" | "compatibility" => Profile::Compatibility,
+                _ => return Err(invalid("core or compat")),
+            };
+        }
+        "RAW_GL_SAMPLES" => {
+            let samples: u8 = value
+                .parse()
+                .map_err(|_| invalid("a sample count like 4"))?;
+            config.samples = if samples == 0 { None } else { Some(samples) };
+        }
+        "RAW_GL_SRGB" => config.srgb = parse_bool(value)?,
+        "RAW_GL_DOUBLE_BUFFER" => config.double_buffer = parse_bool(value)?,
+        "RAW_GL_VSYNC" => config.vsync = parse_bool(value)?,
+        "RAW_GL_BACKEND" => {
+            let backend = match value.to_ascii_lowercase().as_str() {
+                "glx" => Backend::Glx,
+                "wgl" => Backend::Wgl,
+                "cgl" => Backend::Cgl,
+                #[cfg(feature = "mock")]
+                "mock" => Backend::Mock,
+                "egl" => return Err(ConfigError::BackendNotAvailable(value.to_string())),
+                #[cfg(not(feature = "mock"))]
+                "mock" => return Err(ConfigError::BackendNotAvailable(value.to_string())),
+                _ => return Err(invalid("glx, wgl, cgl or mock")),
+            };
+            if !backend_available(backend) {
+                return Err(ConfigError::BackendNotAvailable(value.to_string()));
+            }
+            config.backend = Some(backend);
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Whether contexts can be created with `backend`: the platform's own, or the mock backend.
+fn backend_available(backend: Backend) -> bool {
+    #[cfg(feature = "mock")]
+    if backend == Backend::Mock {
+        return true;
+    }
+
+    backend == platform::BACKEND
+}
+
+/// Builds a [`GlConfig`] starting from [`GlConfig::default`], validating it in
+/// [`GlConfigBuilder::build`].
+#[derive(Clone, Debug)]
+pub struct GlConfigBuilder {
+    config: GlConfig,
+}
+
+impl GlConfigBuilder {
+    pub fn version(mut self, major: u8, minor: u8) -> Self {
+        self.config.version = (major, minor);
+        self
+    }
+
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.config.profile = profile;
+        self
+    }
+
+    pub fn red_bits(mut self, red_bits: u8) -> Self {
+        self.config.red_bits = red_bits;
+        self
+    }
+
+    pub fn green_bits(mut self, green_bits: u8) -> Self {
+        self.config.green_bits = green_bits;
+        self
+    }
+
+    pub fn blue_bits(mut self, blue_bits: u8) -> Self {
+        self.config.blue_bits = blue_bits;
+        self
+    }
+
+    pub fn alpha_bits(mut self, alpha_bits: u8) -> Self {
+        self.config.alpha_bits = alpha_bits;
+        self
+    }
+
+    pub fn depth_bits(mut self, depth_bits: u8) -> Self {
+        self.config.depth_bits = depth_bits;
+        self
+    }
+
+    pub fn stencil_bits(mut self, stencil_bits: u8) -> Self {
+        self.config.stencil_bits = stencil_bits;
+        self
+    }
+
+    pub fn color_format(mut self, color_format: ColorFormat) -> Self {
+        self.config.color_format = color_format;
+        self
+    }
+
+    pub fn samples(mut self, samples: Option<u8>) -> Self {
+        self.config.samples = samples;
+        self
+    }
+
+    pub fn srgb(mut self, srgb: bool) -> Self {
+        self.config.srgb = srgb;
+        self
+    }
+
+    pub fn double_buffer(mut self, double_buffer: bool) -> Self {
+        self.config.double_buffer = double_buffer;
+        self
+    }
+
+    pub fn stereo(mut self, stereo: bool) -> Self {
+        self.config.stereo = stereo;
+        self
+    }
+
+    pub fn vsync(mut self, vsync: bool) -> Self {
+        self.config.vsync = vsync;
+        self
+    }
+
+    pub fn matching(mut self, matching: Matching) -> Self {
+        self.config.matching = matching;
+        self
+    }
+
+    pub fn rendering(mut self, rendering: DirectRendering) -> Self {
+        self.config.rendering = rendering;
+        self
+    }
+
+    pub fn screen(mut self, screen: Option<i32>) -> Self {
+        self.config.screen = screen;
+        self
+    }
+
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.config.backend = Some(backend);
+        self
+    }
+
+    pub fn check_errors(mut self, check_errors: bool) -> Self {
+        self.config.check_errors = check_errors;
+        self
+    }
+
+    pub fn record_trace(mut self, record_trace: Option<PathBuf>) -> Self {
+        self.config.record_trace = record_trace;
+        self
+    }
+
+    pub fn build(self) -> Result<GlConfig, GlError> {
+        self.config.validate().map_err(GlError::InvalidConfig)?;
+        Ok(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A backend that is never available on this platform.
+    fn foreign_backend() -> Backend {
+        if platform::BACKEND == Backend::Glx {
+            Backend::Wgl
+        } else {
+            Backend::Glx
+        }
+    }
+
+    #[test]
+    fn default_is_valid() {
+        assert_eq!(GlConfig::default().validate(), Ok(()));
+        assert!(GlConfig::builder().build().is_ok());
+    }
+
+    #[test]
+    fn invalid_version() {
+        for &version in &[(0, 0), (1, 6), (2, 2), (3, 4), (3, 7), (4, 7), (5, 0)] {
+            let config = GlConfig {
+                version,
+                ..GlConfig::default()
+            };
+            assert_eq!(config.validate(), Err(ConfigError::InvalidVersion(version)));
+        }
+
+        for &version in GL_VERSIONS {
+            let config = GlConfig {
+                version,
+                ..GlConfig::default()
+            };
+            assert_eq!(config.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn invalid_samples() {
+        let config = GlConfig {
+            samples: Some(0),
+            ..GlConfig::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::InvalidSamples(0)));
+
+        let config = GlConfig {
+            samples: Some(4),
+            ..GlConfig::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn invalid_depth_bits() {
+        let config = GlConfig {
+            depth_bits: 33,
+            ..GlConfig::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::InvalidDepthBits(33)));
+
+        let config = GlConfig {
+            depth_bits: 32,
+            ..GlConfig::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn invalid_stencil_bits() {
+        let config = GlConfig {
+            stencil_bits: 9,
+            ..GlConfig::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::InvalidStencilBits(9)));
+    }
+
+    #[test]
+    fn backend_not_available() {
+        let backend = foreign_backend();
+        let config = GlConfig {
+            backend: Some(backend),
+            ..GlConfig::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::BackendNotAvailable(
+                format!("{:?}", backend).to_ascii_lowercase()
+            ))
+        );
+
+        let config = GlConfig {
+            backend: Some(platform::BACKEND),
+            ..GlConfig::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn mock_backend_available() {
+        let config = GlConfig {
+            backend: Some(Backend::Mock),
+            ..GlConfig::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn check_errors_needs_feature() {
+        let config = GlConfig {
+            check_errors: true,
+            ..GlConfig::default()
+        };
+        if cfg!(feature = "gl-functions") {
+            assert_eq!(config.validate(), Ok(()));
+        } else {
+            assert_eq!(
+                config.validate(),
+                Err(ConfigError::FeatureNotEnabled("gl-functions"))
+            );
+        }
+    }
+
+    #[test]
+    fn record_trace_needs_feature() {
+        let config = GlConfig {
+            record_trace: Some(PathBuf::from("trace.bin")),
+            ..GlConfig::default()
+        };
+        if cfg!(feature = "gl-trace") {
+            assert_eq!(config.validate(), Ok(()));
+        } else {
+            assert_eq!(
+                config.validate(),
+                Err(ConfigError::FeatureNotEnabled("gl-trace"))
+            );
+        }
+    }
+
+    #[cfg(feature = "gl-trace")]
+    #[test]
+    fn check_errors_conflicts_with_record_trace() {
+        let config = GlConfig {
+            check_errors: true,
+            record_trace: Some(PathBuf::from("trace.bin")),
+            ..GlConfig::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::ConflictingOptions(
+                "check_errors",
+                "record_trace"
+            ))
+        );
+    }
+
+    #[test]
+    fn builder_validates() {
+        let invalid = |builder: GlConfigBuilder| match builder.build() {
+            Err(GlError::InvalidConfig(error)) => error,
+            result => panic!("expected an invalid config, got {:?}", result),
+        };
+
+        assert_eq!(
+            invalid(GlConfig::builder().version(3, 7)),
+            ConfigError::InvalidVersion((3, 7))
+        );
+        assert_eq!(
+            invalid(GlConfig::builder().samples(Some(0))),
+            ConfigError::InvalidSamples(0)
+        );
+        assert_eq!(
+            invalid(GlConfig::builder().depth_bits(40)),
+            ConfigError::InvalidDepthBits(40)
+        );
+        assert_eq!(
+            invalid(GlConfig::builder().stencil_bits(16)),
+            ConfigError::InvalidStencilBits(16)
+        );
+        assert!(matches!(
+            invalid(GlConfig::builder().backend(foreign_backend())),
+            ConfigError::BackendNotAvailable(_)
+        ));
+
+        if !cfg!(feature = "gl-functions") {
+            assert_eq!(
+                invalid(GlConfig::builder().check_errors(true)),
+                ConfigError::FeatureNotEnabled("gl-functions")
+            );
+        }
+
+        let trace = Some(PathBuf::from("trace.bin"));
+        if cfg!(feature = "gl-trace") {
+            assert_eq!(
+                invalid(GlConfig::builder().check_errors(true).record_trace(trace)),
+                ConfigError::ConflictingOptions("check_errors", "record_trace")
+            );
+        } else {
+            assert_eq!(
+                invalid(GlConfig::builder().record_trace(trace)),
+                ConfigError::FeatureNotEnabled("gl-trace")
+            );
+        }
+
+        let config = GlConfig::builder()
+            .version(4, 5)
+            .samples(Some(4))
+            .depth_bits(32)
+            .build()
+            .unwrap();
+        assert_eq!(config.version, (4, 5));
+        assert_eq!(config.samples, Some(4));
+        assert_eq!(config.depth_bits, 32);
+    }
+
+    #[test]
+    fn backend_override() {
+        let mut config = GlConfig::default();
+        assert_eq!(
+            apply_override(&mut config, "RAW_GL_BACKEND", "egl"),
+            Err(ConfigError::BackendNotAvailable("egl".to_string()))
+        );
+        assert_eq!(
+            apply_override(&mut config, "RAW_GL_BACKEND", "vulkan"),
+            Err(ConfigError::InvalidEnvVar {
+                name: "RAW_GL_BACKEND",
+                value: "vulkan".to_string(),
+                expected: "glx, wgl, cgl or mock",
+            })
+        );
+
+        let result = apply_override(&mut config, "RAW_GL_BACKEND", "mock");
+        if cfg!(feature = "mock") {
+            assert_eq!(result, Ok(()));
+        } else {
+            assert_eq!(
+                result,
+                Err(ConfigError::BackendNotAvailable("mock".to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn version_override() {
+        let mut config = GlConfig::default();
+        assert_eq!(apply_override(&mut config, "RAW_GL_VERSION", "4.5"), Ok(()));
+        assert_eq!(config.version, (4, 5));
+
+        for &value in &["4", "4.", ".5", "4.5.1", "four.five", "300.0"] {
+            assert_eq!(
+                apply_override(&mut config, "RAW_GL_VERSION", value),
+                Err(ConfigError::InvalidEnvVar {
+                    name: "RAW_GL_VERSION",
+                    value: value.to_string(),
+                    expected: "a version like 3.3",
+                })
+            );
+        }
+        assert_eq!(config.version, (4, 5));
+    }
+
+    #[test]
+    fn profile_override() {
+        let mut config = GlConfig::default();
+        assert_eq!(
+            apply_override(&mut config, "RAW_GL_PROFILE", "compat"),
+            Ok(())
+        );
+        assert_eq!(config.profile, Profile::Compatibility);
+        assert_eq!(
+            apply_override(&mut config, "RAW_GL_PROFILE", "Core"),
+            Ok(())
+        );
+        assert_eq!(config.profile, Profile::Core);
+        assert_eq!(
+            apply_override(&mut config, "RAW_GL_PROFILE", "es"),
+            Err(ConfigError::InvalidEnvVar {
+                name: "RAW_GL_PROFILE",
+                value: "es".to_string(),
+                expected: "core or compat",
+            })
+        );
+    }
+
+    #[test]
+    fn samples_override() {
+        let mut config = GlConfig::default();
+        assert_eq!(apply_override(&mut config, "RAW_GL_SAMPLES", "4"), Ok(()));
+        assert_eq!(config.samples, Some(4));
+        assert_eq!(apply_override(&mut config, "RAW_GL_SAMPLES", "0"), Ok(()));
+        assert_eq!(config.samples, None);
+
+        for &value in &["-1", "256", "four"] {
+            assert_eq!(
+                apply_override(&mut config, "RAW_GL_SAMPLES", value),
+                Err(ConfigError::InvalidEnvVar {
+                    name: "RAW_GL_SAMPLES",
+                    value: value.to_string(),
+                    expected: "a sample count like 4",
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn bool_overrides() {
+        let get = |config: &GlConfig, name| match name {
+            "RAW_GL_SRGB" => config.srgb,
+            "RAW_GL_DOUBLE_BUFFER" => config.double_buffer,
+            _ => config.vsync,
+        };
+
+        for &name in &["RAW_GL_SRGB", "RAW_GL_DOUBLE_BUFFER", "RAW_GL_VSYNC"] {
+            let mut config = GlConfig::default();
+            for &(value, expected) in &[
+                ("1", true),
+                ("0", false),
+                ("TRUE", true),
+                ("false", false),
+                ("on", true),
+                ("off", false),
+                ("yes", true),
+                ("No", false),
+            ] {
+                assert_eq!(apply_override(&mut config, name, value), Ok(()));
+                assert_eq!(get(&config, name), expected, "{}={}", name, value);
+            }
+
+            assert_eq!(
+                apply_override(&mut config, name, "2"),
+                Err(ConfigError::InvalidEnvVar {
+                    name,
+                    value: "2".to_string(),
+                    expected: "1, 0, true, false, on, off, yes or no",
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn env_overrides_replaced() {
+        let mut config = GlConfig::default();
+        config.env_overrides.push(EnvOverride {
+            name: "RAW_GL_SAMPLES",
+            value: "stale".to_string(),
+        });
+
+        let config = config.with_env_overrides().unwrap();
+        for applied in config.env_overrides() {
+            assert_eq!(
+                std::env::var(applied.name).ok(),
+                Some(applied.value.clone())
+            );
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Fault injection for the GLX backend, for testing fallback paths. Only available in debug
+//! builds.
+//!
+//! Faults are injected at the driver call, so they produce the same [`GlError`] a real
+//! failure of that call would. They apply to the calling thread until removed.
+//!
+//! [`GlError`]: crate::GlError
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Fault {
+    /// `glXChooseFBConfig` returns no configs.
+    ChooseFbConfig,
+    /// `glXGetProcAddress("glXCreateContextAttribsARB")` returns null.
+    CreateContextAttribsLookup,
+    /// `glXCreateContextAttribsARB` returns null.
+    CreateContext,
+    /// `glXGetProcAddress("glXSwapIntervalEXT")` returns null.
+    SwapIntervalLookup,
+    /// `glXMakeCurrent` returns false when making a context current. Releasing the current
+    /// context still works.
+    MakeCurrent,
+}
+
+thread_local! {
+    static FAULTS: RefCell<HashSet<Fault>> = RefCell::new(HashSet::new());
+}
+
+pub fn inject(fault: Fault) {
+    FAULTS.with(|faults| faults.borrow_mut().insert(fault));
+}
+
+pub fn remove(fault: Fault) {
+    FAULTS.with(|faults| faults.borrow_mut().remove(&fault));
+}
+
+pub fn clear() {
+    FAULTS.with(|faults| faults.borrow_mut().clear());
+}
+
+pub(crate) fn is_injected(fault: Fault) -> bool {
+    FAULTS.with(|faults| faults.borrow().contains(&fault))
+}
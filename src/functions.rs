@@ -0,0 +1,254 @@
+//! A typed, version-aware table of GL functions, loaded once per context.
+//!
+//! ```ignore
+//! unsafe {
+//!     let functions = context.load_functions(&["GL_ARB_bindless_texture"])?;
+//!     functions.ClearColor(1.0, 0.0, 1.0, 1.0);
+//!     functions.Clear(gl::COLOR_BUFFER_BIT);
+//! }
+//! ```
+
+use std::ffi::c_void;
+use std::fmt;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::OnceLock;
+
+use crate::{GlContext, Profile};
+
+/// The generated GL constants and types.
+#[allow(
+    clippy::all,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    dead_code
+)]
+pub mod gl {
+    include!(concat!(env!("OUT_DIR"), "/gl_bindings.rs"));
+}
+
+use self::gl::types;
+
+pub(crate) struct Command {
+    pub(crate) name: &'static str,
+    /// The version from which the command is part of the core profile.
+    core: Option<(u8, u8)>,
+    /// The version from which the command is part of the compatibility profile.
+    compatibility: Option<(u8, u8)>,
+    extensions: &'static [&'static str],
+}
+
+// The command table, a typed method on `GlFunctions` for each command, and the trampolines
+// used by error-checked contexts, and with `gl-trace` the trampolines used by recording
+// contexts and the functions that replay their calls. glPathGlyphIndexRangeNV takes an array
+// by value, which the trampolines have to accept.
+#[allow(
+    clippy::all,
+    non_snake_case,
+    clippy::missing_safety_doc,
+    improper_ctypes_definitions
+)]
+mod commands {
+    use super::*;
+    use crate::checked::{check_errors, checked_address};
+
+    include!(concat!(env!("OUT_DIR"), "/gl_commands.rs"));
+}
+
+#[cfg(feature = "gl-trace")]
+pub(crate) use self::commands::{replay_call, traced_trampoline, PARAMS};
+pub(crate) use self::commands::{trampoline, COMMANDS};
+
+pub(crate) fn find_command(name: &str) -> Option<usize> {
+    COMMANDS
+        .binary_search_by(|command| command.name.cmp(name))
+        .ok()
+}
+
+/// The last address looked up for each command by any context. Trampolines call through it
+/// when the context current on the thread didn't hand them out, e.g. because it isn't
+/// error-checked, which skips checking or recording the call rather than failing it.
+pub(crate) struct FallbackAddresses(OnceLock<Box<[AtomicPtr<c_void>]>>);
+
+impl FallbackAddresses {
+    pub const fn new() -> FallbackAddresses {
+        FallbackAddresses(OnceLock::new())
+    }
+
+    fn addresses(&self) -> &[AtomicPtr<c_void>] {
+        self.0.get_or_init(|| {
+            (0..COMMANDS.len())
+                .map(|_| AtomicPtr::new(std::ptr::null_mut()))
+                .collect()
+        })
+    }
+
+    pub fn set(&self, index: usize, addr: *const c_void) {
+        self.addresses()[index].store(addr as *mut c_void, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, index: usize) -> *const c_void {
+        self.addresses()[index].load(Ordering::Relaxed)
+    }
+}
+
+/// Reports a fatal error from inside a trampoline. Unwinding out of an `extern "system"`
+/// function would abort anyway, without saying why.
+pub(crate) fn abort(message: fmt::Arguments) -> ! {
+    eprintln!("raw-gl-context: {}", message);
+    std::process::abort()
+}
+
+fn check(
+    command: &Command,
+    version: (u8, u8),
+    profile: Profile,
+    extensions: &[String],
+) -> Result<(), FunctionError> {
+    let since = match profile {
+        Profile::Core => command.core,
+        Profile::Compatibility => command.compatibility,
+    };
+
+    if since.is_some_and(|since| since <= version) {
+        return Ok(());
+    }
+
+    if command
+        .extensions
+        .iter()
+        .any(|ext| extensions.iter().any(|e| e == ext))
+    {
+        return Ok(());
+    }
+
+    if let Some(version) = since {
+        Err(FunctionError::RequiresVersion {
+            name: command.name,
+            version,
+        })
+    } else if profile == Profile::Core && command.compatibility.is_some() {
+        Err(FunctionError::RemovedFromCore(command.name))
+    } else {
+        Err(FunctionError::RequiresExtension {
+            name: command.name,
+            extensions: command.extensions,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FunctionError {
+    /// Not the name of a GL function.
+    Unknown(String),
+    /// The function requires a newer GL version than the context provides.
+    RequiresVersion {
+        name: &'static str,
+        version: (u8, u8),
+    },
+    /// The function was removed from the core profile.
+    RemovedFromCore(&'static str),
+    /// The function is only provided by extensions that weren't requested.
+    RequiresExtension {
+        name: &'static str,
+        extensions: &'static [&'static str],
+    },
+    /// A requested extension isn't supported by the context.
+    ExtensionNotSupported(String),
+    /// The function should be available, but the driver didn't return an address for it.
+    NotFound(&'static str),
+}
+
+/// The GL functions available to a context: the core functions up to the context's version and
+/// profile, plus those of the extensions requested in [`GlContext::load_functions`]. Each GL
+/// function is a method named without the `gl` prefix, e.g. `functions.DrawArrays(...)`.
+pub struct GlFunctions {
+    addresses: Box<[*const c_void]>,
+    version: (u8, u8),
+    profile: Profile,
+    extensions: Vec<String>,
+}
+
+impl GlFunctions {
+    pub fn version(&self) -> (u8, u8) {
+        self.version
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    /// The extensions whose functions were loaded.
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    /// Looks up the cached address of `name`, e.g. `"glDrawArrays"`, failing if the function
+    /// isn't available to the context.
+    pub fn get(&self, name: &str) -> Result<*const c_void, FunctionError> {
+        let index = find_command(name).ok_or_else(|| FunctionError::Unknown(name.to_string()))?;
+        check(
+            &COMMANDS[index],
+            self.version,
+            self.profile,
+            &self.extensions,
+        )?;
+
+        let addr = self.addresses[index];
+        if addr.is_null() {
+            return Err(FunctionError::NotFound(COMMANDS[index].name));
+        }
+
+        Ok(addr)
+    }
+
+    fn address(&self, index: usize) -> *const c_void {
+        let addr = self.addresses[index];
+        if addr.is_null() {
+            let command = &COMMANDS[index];
+            match check(command, self.version, self.profile, &self.extensions) {
+                Err(err) => panic!("{} is not available: {:?}", command.name, err),
+                Ok(()) => panic!("{} was not found", command.name),
+            }
+        }
+
+        addr
+    }
+}
+
+impl GlContext {
+    /// Loads the function table for this context. Functions that aren't available to the
+    /// context are left unloaded, and panic if called.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current.
+    pub unsafe fn load_functions(&self, extensions: &[&str]) -> Result<GlFunctions, FunctionError> {
+        for &extension in extensions {
+            if !self.gl_extensions.iter().any(|e| e == extension) {
+                return Err(FunctionError::ExtensionNotSupported(extension.to_string()));
+            }
+        }
+
+        let version = self.config.version;
+        let profile = self.config.profile;
+        let extensions: Vec<String> = extensions.iter().map(|e| e.to_string()).collect();
+
+        let addresses: Box<[*const c_void]> = COMMANDS
+            .iter()
+            .map(
+                |command| match check(command, version, profile, &extensions) {
+                    Ok(()) => self.get_proc_address(command.name),
+                    Err(_) => std::ptr::null(),
+                },
+            )
+            .collect();
+
+        Ok(GlFunctions {
+            addresses,
+            version,
+            profile,
+            extensions,
+        })
+    }
+}
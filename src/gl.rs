@@ -0,0 +1,174 @@
+//! The handful of GL entry points the crate itself needs to inspect a context after creation.
+
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+
+use crate::Profile;
+
+pub type GLenum = u32;
+pub type GLboolean = u8;
+pub type GLint = i32;
+pub type GLuint = u32;
+pub type GLsizei = i32;
+
+pub const VENDOR: GLenum = 0x1F00;
+pub const RENDERER: GLenum = 0x1F01;
+pub const VERSION: GLenum = 0x1F02;
+pub const EXTENSIONS: GLenum = 0x1F03;
+pub const NUM_EXTENSIONS: GLenum = 0x821D;
+pub const SHADING_LANGUAGE_VERSION: GLenum = 0x8B8C;
+pub const CONTEXT_PROFILE_MASK: GLenum = 0x9126;
+pub const CONTEXT_CORE_PROFILE_BIT: GLint = 0x00000001;
+pub const FRONT: GLenum = 0x0404;
+pub const BACK: GLenum = 0x0405;
+pub const READ_BUFFER: GLenum = 0x0C02;
+pub const PACK_ROW_LENGTH: GLenum = 0x0D02;
+pub const PACK_SKIP_ROWS: GLenum = 0x0D03;
+pub const PACK_SKIP_PIXELS: GLenum = 0x0D04;
+pub const PACK_ALIGNMENT: GLenum = 0x0D05;
+pub const UNSIGNED_BYTE: GLenum = 0x1401;
+pub const RGB: GLenum = 0x1907;
+pub const RGBA: GLenum = 0x1908;
+pub const PIXEL_PACK_BUFFER: GLenum = 0x88EB;
+pub const PIXEL_PACK_BUFFER_BINDING: GLenum = 0x88ED;
+pub const READ_FRAMEBUFFER: GLenum = 0x8CA8;
+pub const READ_FRAMEBUFFER_BINDING: GLenum = 0x8CAA;
+pub const FRAMEBUFFER_SRGB: GLenum = 0x8DB9;
+
+type GetString = extern "system" fn(GLenum) -> *const c_char;
+type GetStringi = extern "system" fn(GLenum, GLuint) -> *const c_char;
+type GetIntegerv = extern "system" fn(GLenum, *mut GLint);
+type IsEnabled = extern "system" fn(GLenum) -> GLboolean;
+type Enable = extern "system" fn(GLenum);
+type PixelStorei = extern "system" fn(GLenum, GLint);
+type ReadBuffer = extern "system" fn(GLenum);
+type BindBuffer = extern "system" fn(GLenum, GLuint);
+type BindFramebuffer = extern "system" fn(GLenum, GLuint);
+type ReadPixels = extern "system" fn(GLint, GLint, GLsizei, GLsizei, GLenum, GLenum, *mut c_void);
+
+pub struct Gl {
+    get_string: Option<GetString>,
+    get_stringi: Option<GetStringi>,
+    get_integerv: Option<GetIntegerv>,
+    pub is_enabled: Option<IsEnabled>,
+    pub enable: Option<Enable>,
+    pub disable: Option<Enable>,
+    pub pixel_storei: Option<PixelStorei>,
+    pub read_buffer: Option<ReadBuffer>,
+    pub bind_buffer: Option<BindBuffer>,
+    pub bind_framebuffer: Option<BindFramebuffer>,
+    pub read_pixels: Option<ReadPixels>,
+}
+
+impl Gl {
+    pub fn load(mut get_proc_address: impl FnMut(&str) -> *const c_void) -> Gl {
+        let mut load = |symbol| {
+            let addr = get_proc_address(symbol);
+            if addr.is_null() {
+                None
+            } else {
+                Some(addr)
+            }
+        };
+
+        unsafe {
+            Gl {
+                get_string: load("glGetString")
+                    .map(|addr| std::mem::transmute::<*const c_void, GetString>(addr)),
+                get_stringi: load("glGetStringi")
+                    .map(|addr| std::mem::transmute::<*const c_void, GetStringi>(addr)),
+                get_integerv: load("glGetIntegerv")
+                    .map(|addr| std::mem::transmute::<*const c_void, GetIntegerv>(addr)),
+                is_enabled: load("glIsEnabled")
+                    .map(|addr| std::mem::transmute::<*const c_void, IsEnabled>(addr)),
+                enable: load("glEnable")
+                    .map(|addr| std::mem::transmute::<*const c_void, Enable>(addr)),
+                disable: load("glDisable")
+                    .map(|addr| std::mem::transmute::<*const c_void, Enable>(addr)),
+                pixel_storei: load("glPixelStorei")
+                    .map(|addr| std::mem::transmute::<*const c_void, PixelStorei>(addr)),
+                read_buffer: load("glReadBuffer")
+                    .map(|addr| std::mem::transmute::<*const c_void, ReadBuffer>(addr)),
+                bind_buffer: load("glBindBuffer")
+                    .map(|addr| std::mem::transmute::<*const c_void, BindBuffer>(addr)),
+                bind_framebuffer: load("glBindFramebuffer")
+                    .map(|addr| std::mem::transmute::<*const c_void, BindFramebuffer>(addr)),
+                read_pixels: load("glReadPixels")
+                    .map(|addr| std::mem::transmute::<*const c_void, ReadPixels>(addr)),
+            }
+        }
+    }
+
+    /// Requires the context to be current.
+    pub unsafe fn get_string(&self, name: GLenum) -> Option<String> {
+        let string = (self.get_string?)(name);
+        if string.is_null() {
+            return None;
+        }
+
+        Some(CStr::from_ptr(string).to_string_lossy().into_owned())
+    }
+
+    /// Requires the context to be current.
+    pub unsafe fn get_string_i(&self, name: GLenum, index: GLuint) -> Option<String> {
+        let string = (self.get_stringi?)(name, index);
+        if string.is_null() {
+            return None;
+        }
+
+        Some(CStr::from_ptr(string).to_string_lossy().into_owned())
+    }
+
+    /// Requires the context to be current.
+    pub unsafe fn get_integer(&self, name: GLenum) -> Option<GLint> {
+        let mut value = 0;
+        (self.get_integerv?)(name, &mut value);
+        Some(value)
+    }
+
+    /// The version of the current context, parsed from `GL_VERSION`.
+    pub unsafe fn version(&self) -> Option<(u8, u8)> {
+        parse_version(&self.get_string(VERSION)?)
+    }
+
+    /// The profile of the current context. Contexts older than 3.2 have no core profile.
+    pub unsafe fn profile(&self, version: (u8, u8)) -> Profile {
+        if version >= (3, 2) {
+            if let Some(mask) = self.get_integer(CONTEXT_PROFILE_MASK) {
+                if mask & CONTEXT_CORE_PROFILE_BIT != 0 {
+                    return Profile::Core;
+                }
+            }
+        }
+
+        Profile::Compatibility
+    }
+
+    /// The extensions supported by the current context. `GL_EXTENSIONS` can't be passed to
+    /// glGetString in core profiles, so glGetStringi is used from 3.0 on.
+    pub unsafe fn extensions(&self, version: (u8, u8)) -> Vec<String> {
+        if version >= (3, 0) && self.get_stringi.is_some() {
+            let count = self.get_integer(NUM_EXTENSIONS).unwrap_or(0);
+            (0..count.max(0) as GLuint)
+                .filter_map(|i| self.get_string_i(EXTENSIONS, i))
+                .collect()
+        } else {
+            self.get_string(EXTENSIONS)
+                .map(|extensions| extensions.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// Parses the leading `major.minor` of a `GL_VERSION` string such as
+/// `"4.6 (Core Profile) Mesa 23.2.1"`.
+pub fn parse_version(version: &str) -> Option<(u8, u8)> {
+    let mut numbers = version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|s| !s.is_empty());
+
+    let major = numbers.next()?.parse().ok()?;
+    let minor = numbers.next()?.parse().ok()?;
+
+    Some((major, minor))
+}
@@ -0,0 +1,67 @@
+use std::ops::Deref;
+
+use crate::{GlContext, Profile};
+
+/// A [`glow::Context`] for a [`GlContext`], returned by [`GlContext::glow_context`]. Derefs to
+/// the `glow::Context`. Debug builds check on each deref that the `GlContext` is current on the
+/// calling thread; a reference kept across a change of context isn't checked again.
+pub struct GlowContext<'a> {
+    context: &'a GlContext,
+    glow: glow::Context,
+}
+
+impl GlowContext<'_> {
+    /// The version of the context, as negotiated at creation.
+    pub fn version(&self) -> (u8, u8) {
+        self.context.config.version
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.context.config.profile
+    }
+
+    /// The GL extensions supported by the context. Equivalent to
+    /// [`GlContext::gl_extensions`].
+    pub fn extensions(&self) -> &[String] {
+        self.context.gl_extensions()
+    }
+
+    /// Whether `name` is one of [`GlowContext::extensions`]. Unlike
+    /// [`GlContext::has_extension`], platform extensions aren't included.
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions().iter().any(|extension| extension == name)
+    }
+}
+
+impl Deref for GlowContext<'_> {
+    type Target = glow::Context;
+
+    fn deref(&self) -> &glow::Context {
+        debug_assert!(
+            self.context.is_current(),
+            "glow context used while its GlContext isn't current"
+        );
+
+        &self.glow
+    }
+}
+
+impl GlContext {
+    /// Makes the context current and creates a [`glow::Context`] for it.
+    ///
+    /// # Safety
+    ///
+    /// As for [`GlContext::make_current`]. The returned context must only be used while this
+    /// one is current.
+    pub unsafe fn glow_context(&self) -> GlowContext<'_> {
+        self.make_current();
+
+        let glow =
+            glow::Context::from_loader_function_cstr(|symbol| self.get_proc_address_cstr(symbol));
+
+        GlowContext {
+            context: self,
+            glow,
+        }
+    }
+}
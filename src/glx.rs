@@ -0,0 +1,15 @@
+//! GLX queries that don't need a context.
+
+use std::ffi::c_void;
+
+use crate::{platform, GlConfig};
+
+/// Every framebuffer configuration GLX offers for windows on `screen` of `display`, an Xlib
+/// `Display*`. The version and profile are left at their defaults.
+///
+/// # Safety
+///
+/// `display` must be an open Xlib display, and `screen` one of its screens.
+pub unsafe fn enumerate_configs(display: *mut c_void, screen: i32) -> Vec<GlConfig> {
+    platform::enumerate_configs(display as *mut _, screen)
+}
@@ -0,0 +1,76 @@
+//! Conversions from newer raw-window-handle versions to the 0.3 handles the platform modules
+//! take. Newer versions pass the X display separately from the window.
+//!
+//! There is no Wayland (EGL) backend, so Wayland handles are rejected.
+
+use raw_window_handle::RawWindowHandle;
+
+use crate::GlError;
+
+#[cfg(feature = "rwh_05")]
+pub fn from_rwh_05(
+    window: rwh_05::RawWindowHandle,
+    display: rwh_05::RawDisplayHandle,
+) -> Result<RawWindowHandle, GlError> {
+    match (window, display) {
+        (rwh_05::RawWindowHandle::Xlib(window), rwh_05::RawDisplayHandle::Xlib(display)) => {
+            Ok(RawWindowHandle::Xlib(raw_window_handle::unix::XlibHandle {
+                window: window.window,
+                display: display.display,
+                ..raw_window_handle::unix::XlibHandle::empty()
+            }))
+        }
+        #[cfg(target_os = "windows")]
+        (rwh_05::RawWindowHandle::Win32(window), _) => Ok(RawWindowHandle::Windows(
+            raw_window_handle::windows::WindowsHandle {
+                hwnd: window.hwnd,
+                hinstance: window.hinstance,
+                ..raw_window_handle::windows::WindowsHandle::empty()
+            },
+        )),
+        #[cfg(target_os = "macos")]
+        (rwh_05::RawWindowHandle::AppKit(window), _) => Ok(RawWindowHandle::MacOS(
+            raw_window_handle::macos::MacOSHandle {
+                ns_window: window.ns_window,
+                ns_view: window.ns_view,
+                ..raw_window_handle::macos::MacOSHandle::empty()
+            },
+        )),
+        _ => Err(GlError::InvalidWindowHandle),
+    }
+}
+
+#[cfg(feature = "rwh_06")]
+pub fn from_rwh_06(
+    window: rwh_06::RawWindowHandle,
+    display: rwh_06::RawDisplayHandle,
+) -> Result<RawWindowHandle, GlError> {
+    match (window, display) {
+        (rwh_06::RawWindowHandle::Xlib(window), rwh_06::RawDisplayHandle::Xlib(display)) => {
+            // A missing display means the window is on the default display, which the caller
+            // would have to open; reject it rather than opening a second connection.
+            let display = display.display.ok_or(GlError::InvalidWindowHandle)?;
+            Ok(RawWindowHandle::Xlib(raw_window_handle::unix::XlibHandle {
+                window: window.window,
+                display: display.as_ptr(),
+                ..raw_window_handle::unix::XlibHandle::empty()
+            }))
+        }
+        #[cfg(target_os = "windows")]
+        (rwh_06::RawWindowHandle::Win32(window), _) => Ok(RawWindowHandle::Windows(
+            raw_window_handle::windows::WindowsHandle {
+                hwnd: window.hwnd.get() as *mut _,
+                hinstance: window.hinstance.map_or(0, |h| h.get()) as *mut _,
+                ..raw_window_handle::windows::WindowsHandle::empty()
+            },
+        )),
+        #[cfg(target_os = "macos")]
+        (rwh_06::RawWindowHandle::AppKit(window), _) => Ok(RawWindowHandle::MacOS(
+            raw_window_handle::macos::MacOSHandle {
+                ns_view: window.ns_view.as_ptr(),
+                ..raw_window_handle::macos::MacOSHandle::empty()
+            },
+        )),
+        _ => Err(GlError::InvalidWindowHandle),
+    }
+}
@@ -0,0 +1,71 @@
+use crate::gl;
+use crate::Backend;
+
+/// Renderers that rasterize on the CPU, matched case-insensitively against `GL_RENDERER`.
+const SOFTWARE_RENDERERS: &[&str] = &[
+    "llvmpipe",
+    "softpipe",
+    "swrast",
+    "software rasterizer",
+    "gdi generic",
+    "apple software renderer",
+];
+
+/// The vendor and version strings of the GLX client library and server.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GlxInfo {
+    pub client_vendor: String,
+    pub client_version: String,
+    pub server_vendor: String,
+    pub server_version: String,
+}
+
+/// The GPU and driver behind a context, queried once at creation.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RendererInfo {
+    pub vendor: String,
+    pub renderer: String,
+    pub version: String,
+    /// Empty for contexts without GLSL support.
+    pub shading_language_version: String,
+    /// `None` on backends other than GLX.
+    pub glx: Option<GlxInfo>,
+    pub backend: Backend,
+    pub direct: bool,
+    /// Whether `renderer` is a software rasterizer such as llvmpipe, softpipe or swrast.
+    pub software: bool,
+}
+
+impl RendererInfo {
+    /// Requires the context to be current.
+    pub(crate) unsafe fn query(
+        gl: &gl::Gl,
+        glx: Option<GlxInfo>,
+        backend: Backend,
+        direct: bool,
+    ) -> RendererInfo {
+        let renderer = gl.get_string(gl::RENDERER).unwrap_or_default();
+
+        RendererInfo {
+            vendor: gl.get_string(gl::VENDOR).unwrap_or_default(),
+            software: is_software_renderer(&renderer),
+            renderer,
+            version: gl.get_string(gl::VERSION).unwrap_or_default(),
+            shading_language_version: gl
+                .get_string(gl::SHADING_LANGUAGE_VERSION)
+                .unwrap_or_default(),
+            glx,
+            backend,
+            direct,
+        }
+    }
+}
+
+fn is_software_renderer(renderer: &str) -> bool {
+    let renderer = renderer.to_ascii_lowercase();
+    SOFTWARE_RENDERERS
+        .iter()
+        .any(|software| renderer.contains(software))
+}
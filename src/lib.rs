@@ -1,7 +1,45 @@
-use raw_window_handle::HasRawWindowHandle;
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 
-use std::ffi::c_void;
+use std::ffi::{c_void, CStr, CString};
 use std::marker::PhantomData;
+use std::path::PathBuf;
+
+#[macro_use]
+mod trace;
+
+mod backend;
+#[cfg(feature = "gl-trace")]
+mod capture;
+#[cfg(feature = "gl-functions")]
+mod checked;
+mod config;
+#[cfg(all(debug_assertions, target_os = "linux"))]
+pub mod fault;
+#[cfg(feature = "gl-functions")]
+pub mod functions;
+mod gl;
+#[cfg(feature = "glow")]
+mod glow_context;
+#[cfg(target_os = "linux")]
+pub mod glx;
+#[cfg(any(feature = "rwh_05", feature = "rwh_06"))]
+mod handle;
+mod info;
+#[cfg(target_os = "linux")]
+pub mod mesa;
+mod readback;
+#[cfg(feature = "gl-trace")]
+pub mod replay;
+#[cfg(all(feature = "testing", target_os = "linux"))]
+pub mod testing;
+
+#[cfg(feature = "gl-functions")]
+pub use checked::GlCallError;
+pub use config::{ConfigError, EnvOverride, GlConfigBuilder};
+#[cfg(feature = "glow")]
+pub use glow_context::GlowContext;
+pub use info::{GlxInfo, RendererInfo};
+pub use readback::{ColorSpace, Image, PixelFormat, ReadBuffer, Region};
 
 #[cfg(target_os = "windows")]
 mod win;
@@ -18,7 +56,12 @@ mod macos;
 #[cfg(target_os = "macos")]
 use macos as platform;
 
+#[cfg(feature = "mock")]
+pub mod mock;
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct GlConfig {
     pub version: (u8, u8),
     pub profile: Profile,
@@ -28,10 +71,36 @@ pub struct GlConfig {
     pub alpha_bits: u8,
     pub depth_bits: u8,
     pub stencil_bits: u8,
+    pub color_format: ColorFormat,
     pub samples: Option<u8>,
     pub srgb: bool,
     pub double_buffer: bool,
+    /// Quad-buffered stereo. Combined with `double_buffer`, the context has separate left and
+    /// right back buffers.
+    pub stereo: bool,
+    /// Sets the swap interval to 1, or 0 if false. Without the platform's swap control
+    /// extension (`GLX_EXT_swap_control`, `WGL_EXT_swap_control`) this is ignored and the
+    /// driver's default interval is kept, with a warning logged if `tracing` is enabled.
     pub vsync: bool,
+    pub matching: Matching,
+    pub rendering: DirectRendering,
+    /// The X screen to choose framebuffer configurations for. Defaults to the screen the
+    /// window is on. Ignored on other platforms.
+    pub screen: Option<i32>,
+    /// The backend to create the context with. Defaults to the platform's; the only other
+    /// choice is `Backend::Mock`, with the `mock` feature.
+    pub backend: Option<Backend>,
+    /// Makes [`GlContext::get_proc_address`] return wrappers that check `glGetError` after
+    /// every call, and print the error and abort, or call the callback set with
+    /// [`GlContext::set_error_callback`], on an error. Requires the `gl-functions` feature.
+    /// Meant for debugging, as it costs a round trip to the driver per call.
+    pub check_errors: bool,
+    /// Records every call made through functions from [`GlContext::get_proc_address`] to this
+    /// file, with the data uploaded through them, for the `replay` module. Requires the
+    /// `gl-trace` feature, and can't be combined with `check_errors`.
+    pub record_trace: Option<PathBuf>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    env_overrides: Vec<EnvOverride>,
 }
 
 impl Default for GlConfig {
@@ -45,56 +114,593 @@ impl Default for GlConfig {
             alpha_bits: 8,
             depth_bits: 24,
             stencil_bits: 8,
+            color_format: ColorFormat::Fixed,
             samples: None,
             srgb: true,
             double_buffer: true,
+            stereo: false,
             vsync: false,
+            matching: Matching::AtLeast,
+            rendering: DirectRendering::Prefer,
+            screen: None,
+            backend: None,
+            check_errors: false,
+            record_trace: None,
+            env_overrides: Vec::new(),
+        }
+    }
+}
+
+impl GlConfig {
+    pub(crate) fn matches_exactly(&self, actual: &GlConfig) -> bool {
+        self.red_bits == actual.red_bits
+            && self.green_bits == actual.green_bits
+            && self.blue_bits == actual.blue_bits
+            && self.alpha_bits == actual.alpha_bits
+            && self.depth_bits == actual.depth_bits
+            && self.stencil_bits == actual.stencil_bits
+            && self.samples == actual.samples
+    }
+
+    fn distance(&self, actual: &GlConfig) -> u32 {
+        let diff = |a: u8, b: u8| (a as i32 - b as i32).unsigned_abs();
+
+        diff(self.red_bits, actual.red_bits)
+            + diff(self.green_bits, actual.green_bits)
+            + diff(self.blue_bits, actual.blue_bits)
+            + diff(self.alpha_bits, actual.alpha_bits)
+            + diff(self.depth_bits, actual.depth_bits)
+            + diff(self.stencil_bits, actual.stencil_bits)
+            + diff(self.samples.unwrap_or(0), actual.samples.unwrap_or(0))
+    }
+
+    /// The config whose sizes should be passed to the platform as minimums. In exact mode
+    /// these are relaxed so that near misses can be reported as alternatives.
+    pub(crate) fn minimums(&self) -> GlConfig {
+        match self.matching {
+            Matching::AtLeast => self.clone(),
+            Matching::Exact => GlConfig {
+                red_bits: 0,
+                green_bits: 0,
+                blue_bits: 0,
+                alpha_bits: 0,
+                depth_bits: 0,
+                stencil_bits: 0,
+                samples: None,
+                ..self.clone()
+            },
+        }
+    }
+
+    /// The candidates closest to `self`, nearest first, without duplicates.
+    pub(crate) fn closest(&self, candidates: &[GlConfig]) -> Vec<GlConfig> {
+        const MAX_ALTERNATIVES: usize = 4;
+
+        let mut closest: Vec<GlConfig> = Vec::new();
+        for candidate in candidates {
+            if !closest.iter().any(|c| c.matches_exactly(candidate)) {
+                closest.push(candidate.clone());
+            }
         }
+        closest.sort_by_key(|candidate| self.distance(candidate));
+        closest.truncate(MAX_ALTERNATIVES);
+
+        closest
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Profile {
     Compatibility,
     Core,
 }
 
+/// How the color, depth, stencil and sample counts of a [`GlConfig`] are matched against the
+/// available framebuffer configurations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Matching {
+    /// The requested sizes are minimums, and the platform picks its preferred configuration.
+    AtLeast,
+    /// Only a configuration with exactly the requested sizes is accepted.
+    Exact,
+}
+
+/// Whether the context should render directly to the hardware or be routed through the X
+/// server (GLX indirect rendering). Other platforms always render directly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DirectRendering {
+    /// Fail with [`GlError::DirectRenderingUnavailable`] if the context isn't direct.
+    Require,
+    /// Ask for a direct context, but accept an indirect one.
+    Prefer,
+    /// Ask for an indirect context.
+    Indirect,
+}
+
+/// The numeric representation of the default framebuffer's color channels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorFormat {
+    /// Unsigned normalized channels, e.g. RGBA8 or RGB10_A2.
+    Fixed,
+    /// Signed floating-point channels, e.g. RGBA16F (ARB_color_buffer_float).
+    Float,
+    /// Unsigned packed floating-point channels, e.g. R11F_G11F_B10F (EXT_packed_float).
+    PackedFloat,
+}
+
+/// The platform API used to create contexts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Backend {
+    Glx,
+    Wgl,
+    Cgl,
+    /// The backend of the `mock` feature, only used when asked for with [`GlConfig::backend`].
+    #[cfg(feature = "mock")]
+    Mock,
+}
+
+/// The native objects behind a [`GlContext`], for interop with other libraries. There is no
+/// EGL backend, so there are no EGL handles.
+#[derive(Clone, Copy, Debug)]
+pub enum RawGlHandles {
+    Glx {
+        /// The `Display*`.
+        display: *mut c_void,
+        /// The `GLXContext`.
+        context: *mut c_void,
+        /// The `GLXDrawable`, i.e. the window.
+        drawable: std::os::raw::c_ulong,
+        /// The `GLXFBConfig` the context was created with.
+        fb_config: *mut c_void,
+        visual_id: std::os::raw::c_ulong,
+    },
+    Wgl {
+        hwnd: *mut c_void,
+        hdc: *mut c_void,
+        hglrc: *mut c_void,
+    },
+    Cgl {
+        /// The `NSOpenGLView`.
+        ns_view: *mut c_void,
+        /// The `NSOpenGLContext`.
+        ns_context: *mut c_void,
+        /// The `CGLContextObj` behind `ns_context`.
+        cgl_context: *mut c_void,
+    },
+    #[cfg(feature = "mock")]
+    Mock { id: usize },
+}
+
+/// Whether a [`GlContext`] wrapping a foreign context destroys it when dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ownership {
+    Owned,
+    Borrowed,
+}
+
+/// Information about how a context was created, for debugging.
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics {
+    pub env_overrides: Vec<EnvOverride>,
+}
+
 #[derive(Debug)]
 pub enum GlError {
     InvalidWindowHandle,
     VersionNotSupported,
+    FormatNotSupported,
+    StereoNotSupported,
+    DirectRenderingUnavailable,
+    /// [`Matching::Exact`] was requested and no configuration matched exactly. Contains the
+    /// closest available configurations, nearest first.
+    NoExactMatch(Vec<GlConfig>),
+    InvalidConfig(ConfigError),
     CreationFailed,
+    /// The file for [`GlConfig::record_trace`] couldn't be created.
+    TraceFile(std::io::Error),
+    /// The context doesn't provide a GL function the call needs.
+    FunctionNotAvailable(&'static str),
 }
 
 pub struct GlContext {
-    context: platform::GlContext,
+    context: backend::Context,
+    config: GlConfig,
+    gl_extensions: Vec<String>,
+    gl: gl::Gl,
+    info: RendererInfo,
+    diagnostics: Diagnostics,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "gl-functions")]
+    error_checker: Option<std::rc::Rc<checked::ErrorChecker>>,
+    #[cfg(feature = "gl-trace")]
+    recorder: Option<std::rc::Rc<capture::Recorder>>,
     phantom: PhantomData<*mut ()>,
 }
 
 impl GlContext {
+    /// Creates a context for `parent`'s window.
+    ///
+    /// # Safety
+    ///
+    /// The window handle must be valid and outlive the context. On X11, its `Display*` must
+    /// also outlive the context.
     pub unsafe fn create(
         parent: &impl HasRawWindowHandle,
         config: GlConfig,
     ) -> Result<GlContext, GlError> {
-        platform::GlContext::create(parent, config).map(|context| GlContext {
+        Self::create_from_handle(parent.raw_window_handle(), config)
+    }
+
+    /// Creates a context for a window implementing the raw-window-handle 0.5 traits.
+    ///
+    /// # Safety
+    ///
+    /// As for [`GlContext::create`].
+    #[cfg(feature = "rwh_05")]
+    pub unsafe fn create_rwh_05(
+        parent: &(impl rwh_05::HasRawWindowHandle + rwh_05::HasRawDisplayHandle),
+        config: GlConfig,
+    ) -> Result<GlContext, GlError> {
+        let handle = handle::from_rwh_05(parent.raw_window_handle(), parent.raw_display_handle())?;
+        Self::create_from_handle(handle, config)
+    }
+
+    /// Creates a context for a window implementing the raw-window-handle 0.6 traits.
+    ///
+    /// # Safety
+    ///
+    /// As for [`GlContext::create`].
+    #[cfg(feature = "rwh_06")]
+    pub unsafe fn create_rwh_06(
+        parent: &(impl rwh_06::HasWindowHandle + rwh_06::HasDisplayHandle),
+        config: GlConfig,
+    ) -> Result<GlContext, GlError> {
+        let window = parent
+            .window_handle()
+            .map_err(|_| GlError::InvalidWindowHandle)?;
+        let display = parent
+            .display_handle()
+            .map_err(|_| GlError::InvalidWindowHandle)?;
+        let handle = handle::from_rwh_06(window.as_raw(), display.as_raw())?;
+        Self::create_from_handle(handle, config)
+    }
+
+    unsafe fn create_from_handle(
+        handle: RawWindowHandle,
+        config: GlConfig,
+    ) -> Result<GlContext, GlError> {
+        config.validate().map_err(GlError::InvalidConfig)?;
+
+        let diagnostics = Diagnostics {
+            env_overrides: config.env_overrides.clone(),
+        };
+
+        #[cfg(feature = "tracing")]
+        let span = context_span();
+        #[cfg(feature = "tracing")]
+        let _enter = span.enter();
+
+        #[cfg(feature = "gl-functions")]
+        let check_errors = config.check_errors;
+        #[cfg(feature = "gl-trace")]
+        let record_trace = config.record_trace.clone();
+
+        let step = trace::step("create");
+        let context = backend::Context::create(handle, config)?;
+        step.end();
+
+        let context = Self::from_platform(
+            context,
+            diagnostics,
+            #[cfg(feature = "tracing")]
+            span.clone(),
+        );
+        #[cfg(feature = "gl-functions")]
+        let context = context.with_error_checking(check_errors);
+        #[cfg(feature = "gl-trace")]
+        let context = match record_trace {
+            Some(path) => context.with_recording(&path)?,
+            None => context,
+        };
+
+        Ok(context)
+    }
+
+    /// Wraps a context created outside this crate. The handles must belong to the current
+    /// platform's backend, or to the mock backend. The context is made current on the calling
+    /// thread to query its version and extensions, so it must not be current on another thread.
+    /// Whatever was current on the thread before is made current again afterwards.
+    ///
+    /// With [`Ownership::Borrowed`], the native context is left alive when the `GlContext` is
+    /// dropped.
+    ///
+    /// # Safety
+    ///
+    /// The handles must be valid, and outlive the `GlContext` if borrowed. An owned context
+    /// must not be destroyed by anything else.
+    pub unsafe fn from_raw(
+        handles: RawGlHandles,
+        ownership: Ownership,
+    ) -> Result<GlContext, GlError> {
+        #[cfg(feature = "tracing")]
+        let span = context_span();
+        #[cfg(feature = "tracing")]
+        let _enter = span.enter();
+
+        let context = backend::Context::from_raw(handles, ownership)?;
+
+        Ok(Self::from_platform(
             context,
+            Diagnostics::default(),
+            #[cfg(feature = "tracing")]
+            span.clone(),
+        ))
+    }
+
+    unsafe fn from_platform(
+        context: backend::Context,
+        diagnostics: Diagnostics,
+        #[cfg(feature = "tracing")] span: tracing::Span,
+    ) -> GlContext {
+        // The platform reports the framebuffer it chose; the version and profile are read back
+        // from the context, since drivers may return a newer version than requested.
+        let mut config = context.actual_config().clone();
+        config.backend = Some(context.backend());
+        // Whatever was current on the thread, even another library's context, is made current
+        // again afterwards.
+        let previous = context.make_current_saving();
+        let gl = gl::Gl::load(|symbol| {
+            with_cstr(symbol, |symbol| context.get_proc_address_cstr(symbol))
+        });
+        if let Some(version) = gl.version() {
+            config.version = version;
+            config.profile = gl.profile(version);
+        }
+        let gl_extensions = gl.extensions(config.version);
+        let info = RendererInfo::query(
+            &gl,
+            context.glx_info(),
+            context.backend(),
+            context.is_direct(),
+        );
+        debug!(
+            version = ?config.version,
+            profile = ?config.profile,
+            renderer = %info.renderer,
+            "context ready"
+        );
+        context.restore(previous);
+
+        GlContext {
+            context,
+            config,
+            gl_extensions,
+            gl,
+            info,
+            diagnostics,
+            #[cfg(feature = "tracing")]
+            span,
+            #[cfg(feature = "gl-functions")]
+            error_checker: None,
+            #[cfg(feature = "gl-trace")]
+            recorder: None,
             phantom: PhantomData,
-        })
+        }
+    }
+
+    #[cfg(feature = "gl-functions")]
+    fn with_error_checking(mut self, check_errors: bool) -> GlContext {
+        if check_errors {
+            let get_error = self.context_proc_address("glGetError");
+            self.error_checker = checked::ErrorChecker::new(get_error).map(std::rc::Rc::new);
+        }
+
+        self
+    }
+
+    #[cfg(feature = "gl-trace")]
+    fn with_recording(mut self, path: &std::path::Path) -> Result<GlContext, GlError> {
+        let recorder = capture::Recorder::create(
+            path,
+            self.context_proc_address("glGetIntegerv"),
+            self.config.version,
+            self.config.profile,
+        )
+        .map_err(GlError::TraceFile)?;
+        self.recorder = Some(std::rc::Rc::new(recorder));
+
+        Ok(self)
+    }
+
+    /// The context's own address for `symbol`, never wrapped in a trampoline.
+    fn context_proc_address(&self, symbol: &str) -> *const c_void {
+        with_cstr(symbol, |symbol| self.context.get_proc_address_cstr(symbol))
     }
 
+    /// Makes the context current on the calling thread.
+    ///
+    /// # Safety
+    ///
+    /// The context must not be current on another thread.
     pub unsafe fn make_current(&self) {
         self.context.make_current();
+        #[cfg(feature = "gl-functions")]
+        checked::make_current(self.error_checker.as_ref());
+        #[cfg(feature = "gl-trace")]
+        capture::make_current(self.recorder.as_ref());
     }
 
+    /// Releases the context current on the calling thread.
+    ///
+    /// # Safety
+    ///
+    /// GL functions must not be called on the thread until a context is made current again.
     pub unsafe fn make_not_current(&self) {
         self.context.make_not_current();
+        #[cfg(feature = "gl-functions")]
+        checked::make_current(None);
+        #[cfg(feature = "gl-trace")]
+        capture::make_current(None);
     }
 
+    /// Returns null if the function isn't found or `symbol` contains a NUL byte. With
+    /// [`GlConfig::check_errors`] or [`GlConfig::record_trace`], GL functions are returned
+    /// wrapped in error checking or recording.
     pub fn get_proc_address(&self, symbol: &str) -> *const c_void {
-        self.context.get_proc_address(symbol)
+        let addr = self.context_proc_address(symbol);
+        #[cfg(feature = "gl-functions")]
+        if let Some(checker) = &self.error_checker {
+            return checker.wrap(symbol, addr);
+        }
+        #[cfg(feature = "gl-trace")]
+        if let Some(recorder) = &self.recorder {
+            return recorder.wrap(symbol, addr);
+        }
+
+        addr
+    }
+
+    /// Returns null if the function isn't found.
+    pub fn get_proc_address_cstr(&self, symbol: &CStr) -> *const c_void {
+        let addr = self.context.get_proc_address_cstr(symbol);
+        #[cfg(feature = "gl-functions")]
+        if let (Some(checker), Ok(symbol)) = (&self.error_checker, symbol.to_str()) {
+            return checker.wrap(symbol, addr);
+        }
+        #[cfg(feature = "gl-trace")]
+        if let (Some(recorder), Ok(symbol)) = (&self.recorder, symbol.to_str()) {
+            return recorder.wrap(symbol, addr);
+        }
+
+        addr
+    }
+
+    /// Sets the function called with errors caught by [`GlConfig::check_errors`], instead of
+    /// aborting. The callback can't unwind back into GL, so a panic in it aborts too. Does
+    /// nothing if error checking is off.
+    #[cfg(feature = "gl-functions")]
+    pub fn set_error_callback(&self, callback: impl Fn(&GlCallError) + 'static) {
+        if let Some(checker) = &self.error_checker {
+            checker.set_callback(Some(Box::new(callback)));
+        }
     }
 
     pub fn swap_buffers(&self) {
+        #[cfg(feature = "gl-trace")]
+        if let Some(recorder) = &self.recorder {
+            recorder.swap();
+        }
         self.context.swap_buffers();
     }
+
+    /// The configuration that was actually chosen, which may differ from the one passed to
+    /// [`GlContext::create`].
+    pub fn actual_config(&self) -> &GlConfig {
+        &self.config
+    }
+
+    pub fn is_direct(&self) -> bool {
+        self.context.is_direct()
+    }
+
+    pub fn raw_handles(&self) -> RawGlHandles {
+        self.context.raw_handles()
+    }
+
+    /// Whether the context is current on the calling thread.
+    pub fn is_current(&self) -> bool {
+        self.context.is_current()
+    }
+
+    /// Whether `name` is in [`GlContext::gl_extensions`] or
+    /// [`GlContext::platform_extensions`].
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.gl_extensions.iter().any(|e| e == name)
+            || self.context.platform_extensions().iter().any(|e| e == name)
+    }
+
+    /// The GL extensions supported by the context, queried once at creation.
+    pub fn gl_extensions(&self) -> &[String] {
+        &self.gl_extensions
+    }
+
+    /// The window-system (GLX or WGL) extensions supported for the context's display and
+    /// screen, queried once at creation. Empty on macOS.
+    pub fn platform_extensions(&self) -> &[String] {
+        self.context.platform_extensions()
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.context.backend()
+    }
+
+    /// The GPU, driver and window-system versions behind the context.
+    pub fn info(&self) -> &RendererInfo {
+        &self.info
+    }
+
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// The span the context's creation was logged in. Entering it tags other events with the
+    /// context's id.
+    #[cfg(feature = "tracing")]
+    pub fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+}
+
+impl Drop for GlContext {
+    fn drop(&mut self) {
+        // The trampolines' thread-locals may still hold this context's checker or recorder,
+        // which would otherwise keep the trace unflushed until the thread exits.
+        #[cfg(feature = "gl-functions")]
+        if let Some(checker) = &self.error_checker {
+            checked::release(checker);
+        }
+        #[cfg(feature = "gl-trace")]
+        if let Some(recorder) = &self.recorder {
+            capture::release(recorder);
+            recorder.flush();
+        }
+    }
+}
+
+/// A span identifying one context, so that logs from several windows can be told apart.
+#[cfg(feature = "tracing")]
+fn context_span() -> tracing::Span {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+    tracing::info_span!("gl_context", id = NEXT_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Calls `lookup` with `symbol` as a C string, copied into a stack buffer unless it's unusually
+/// long. Returns null if `symbol` contains a NUL byte.
+pub(crate) fn with_cstr(
+    symbol: &str,
+    lookup: impl FnOnce(&CStr) -> *const c_void,
+) -> *const c_void {
+    const MAX_LEN: usize = 128;
+
+    if symbol.len() < MAX_LEN {
+        let mut buffer = [0u8; MAX_LEN];
+        buffer[..symbol.len()].copy_from_slice(symbol.as_bytes());
+        match CStr::from_bytes_with_nul(&buffer[..=symbol.len()]) {
+            Ok(symbol) => lookup(symbol),
+            Err(_) => std::ptr::null(),
+        }
+    } else {
+        match CString::new(symbol) {
+            Ok(symbol) => lookup(&symbol),
+            Err(_) => std::ptr::null(),
+        }
+    }
 }
@@ -1,14 +1,13 @@
-use std::ffi::c_void;
-use std::str::FromStr;
+use std::ffi::{c_void, CStr};
 
-use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use raw_window_handle::RawWindowHandle;
 
 use cocoa::appkit::{
     NSOpenGLContext, NSOpenGLContextParameter, NSOpenGLPFAAccelerated, NSOpenGLPFAAlphaSize,
-    NSOpenGLPFAColorSize, NSOpenGLPFADepthSize, NSOpenGLPFADoubleBuffer, NSOpenGLPFAMultisample,
-    NSOpenGLPFAOpenGLProfile, NSOpenGLPFASampleBuffers, NSOpenGLPFASamples, NSOpenGLPFAStencilSize,
-    NSOpenGLPixelFormat, NSOpenGLProfileVersion3_2Core, NSOpenGLProfileVersion4_1Core,
-    NSOpenGLProfileVersionLegacy, NSOpenGLView, NSView,
+    NSOpenGLPFAColorFloat, NSOpenGLPFAColorSize, NSOpenGLPFADepthSize, NSOpenGLPFADoubleBuffer,
+    NSOpenGLPFAMultisample, NSOpenGLPFAOpenGLProfile, NSOpenGLPFASampleBuffers, NSOpenGLPFASamples,
+    NSOpenGLPFAStencilSize, NSOpenGLPFAStereo, NSOpenGLPixelFormat, NSOpenGLProfileVersion3_2Core,
+    NSOpenGLProfileVersion4_1Core, NSOpenGLProfileVersionLegacy, NSOpenGLView, NSView,
 };
 use cocoa::base::{id, nil, YES};
 
@@ -18,19 +17,123 @@ use core_foundation::string::CFString;
 
 use objc::{msg_send, sel, sel_impl};
 
-use crate::{GlConfig, GlError, Profile};
+use crate::{
+    Backend, ColorFormat, GlConfig, GlError, GlxInfo, Matching, Ownership, Profile, RawGlHandles,
+};
+
+pub const BACKEND: Backend = Backend::Cgl;
+
+/// The context current on a thread, saved to be restored later.
+pub struct Previous {
+    context: id,
+}
 
 pub struct GlContext {
     view: id,
     context: id,
+    config: GlConfig,
+    ownership: Ownership,
+}
+
+/// Reads back the attributes of `pixel_format`, taking the rest from `requested`.
+unsafe fn describe_pixel_format(pixel_format: id, requested: &GlConfig) -> GlConfig {
+    let get_value = |attrib| {
+        let mut value = 0;
+        pixel_format.getValues_forAttribute_forVirtualScreen_(&mut value, attrib, 0);
+        value
+    };
+
+    // NSOpenGLPFAColorSize only reports the combined size of the color channels, with or
+    // without alpha. If it matches the requested sizes, their split between the channels is
+    // assumed; otherwise the size is split evenly.
+    let alpha_bits = get_value(NSOpenGLPFAAlphaSize);
+    let color_bits = get_value(NSOpenGLPFAColorSize);
+    let requested_bits =
+        requested.red_bits as i32 + requested.green_bits as i32 + requested.blue_bits as i32;
+    let (red_bits, green_bits, blue_bits) =
+        if color_bits == requested_bits || color_bits - alpha_bits == requested_bits {
+            (
+                requested.red_bits,
+                requested.green_bits,
+                requested.blue_bits,
+            )
+        } else {
+            let rgb_bits = if color_bits % 3 == 0 {
+                color_bits
+            } else {
+                color_bits - alpha_bits
+            };
+            let channel_bits = (rgb_bits / 3) as u8;
+            (channel_bits, channel_bits, channel_bits)
+        };
+
+    GlConfig {
+        red_bits,
+        green_bits,
+        blue_bits,
+        alpha_bits: alpha_bits as u8,
+        depth_bits: get_value(NSOpenGLPFADepthSize) as u8,
+        stencil_bits: get_value(NSOpenGLPFAStencilSize) as u8,
+        color_format: if get_value(NSOpenGLPFAColorFloat) != 0 {
+            ColorFormat::Float
+        } else {
+            ColorFormat::Fixed
+        },
+        samples: if get_value(NSOpenGLPFASampleBuffers) != 0 {
+            Some(get_value(NSOpenGLPFASamples) as u8)
+        } else {
+            None
+        },
+        double_buffer: get_value(NSOpenGLPFADoubleBuffer) != 0,
+        stereo: get_value(NSOpenGLPFAStereo) != 0,
+        ..requested.clone()
+    }
+}
+
+/// Chooses a pixel format for `config` and the `NSOpenGLPFAOpenGLProfile` `version`, or
+/// returns nil if there is none. CGL has no packed float formats.
+unsafe fn choose_pixel_format(config: &GlConfig, version: u32) -> id {
+    #[rustfmt::skip]
+    let mut attrs = vec![
+        NSOpenGLPFAOpenGLProfile as u32, version,
+        NSOpenGLPFAColorSize as u32, (config.red_bits + config.blue_bits + config.green_bits) as u32,
+        NSOpenGLPFAAlphaSize as u32, config.alpha_bits as u32,
+        NSOpenGLPFADepthSize as u32, config.depth_bits as u32,
+        NSOpenGLPFAStencilSize as u32, config.stencil_bits as u32,
+        NSOpenGLPFAAccelerated as u32,
+    ];
+
+    if config.samples.is_some() {
+        #[rustfmt::skip]
+        attrs.extend_from_slice(&[
+            NSOpenGLPFAMultisample as u32,
+            NSOpenGLPFASampleBuffers as u32, 1,
+            NSOpenGLPFASamples as u32, config.samples.unwrap() as u32,
+        ]);
+    }
+
+    if config.double_buffer {
+        attrs.push(NSOpenGLPFADoubleBuffer as u32);
+    }
+
+    if config.stereo {
+        attrs.push(NSOpenGLPFAStereo as u32);
+    }
+
+    match config.color_format {
+        ColorFormat::Fixed => {}
+        ColorFormat::Float => attrs.push(NSOpenGLPFAColorFloat as u32),
+        ColorFormat::PackedFloat => return nil,
+    }
+
+    attrs.push(0);
+
+    NSOpenGLPixelFormat::alloc(nil).initWithAttributes_(&attrs)
 }
 
 impl GlContext {
-    pub unsafe fn create(
-        parent: &impl HasRawWindowHandle,
-        config: GlConfig,
-    ) -> Result<GlContext, GlError> {
-        let handle = if let RawWindowHandle::MacOS(handle) = parent.raw_window_handle() {
+    pub unsafe fn create(handle: RawWindowHandle, config: GlConfig) -> Result<GlContext, GlError> {
+        let handle = if let RawWindowHandle::MacOS(handle) = handle {
             handle
         } else {
             return Err(GlError::InvalidWindowHandle);
@@ -52,37 +155,43 @@ impl GlContext {
             return Err(GlError::VersionNotSupported);
         };
 
-        #[rustfmt::skip]
-        let mut attrs = vec![
-            NSOpenGLPFAOpenGLProfile as u32, version as u32,
-            NSOpenGLPFAColorSize as u32, (config.red_bits + config.blue_bits + config.green_bits) as u32,
-            NSOpenGLPFAAlphaSize as u32, config.alpha_bits as u32,
-            NSOpenGLPFADepthSize as u32, config.depth_bits as u32,
-            NSOpenGLPFAStencilSize as u32, config.stencil_bits as u32,
-            NSOpenGLPFAAccelerated as u32,
-        ];
-
-        if config.samples.is_some() {
-            #[rustfmt::skip]
-            attrs.extend_from_slice(&[
-                NSOpenGLPFAMultisample as u32,
-                NSOpenGLPFASampleBuffers as u32, 1,
-                NSOpenGLPFASamples as u32, config.samples.unwrap() as u32,
-            ]);
-        }
-
-        if config.double_buffer {
-            attrs.push(NSOpenGLPFADoubleBuffer as u32);
-        }
+        let pixel_format = choose_pixel_format(&config, version as u32);
 
-        attrs.push(0);
+        if pixel_format == nil {
+            if config.color_format != ColorFormat::Fixed {
+                let fixed = GlConfig {
+                    color_format: ColorFormat::Fixed,
+                    ..config.clone()
+                };
+                let pixel_format = choose_pixel_format(&fixed, version as u32);
+                if pixel_format != nil {
+                    let () = msg_send![pixel_format, release];
+                    return Err(GlError::FormatNotSupported);
+                }
+            }
 
-        let pixel_format = NSOpenGLPixelFormat::alloc(nil).initWithAttributes_(&attrs);
+            if config.stereo {
+                let mono = GlConfig {
+                    stereo: false,
+                    ..config.clone()
+                };
+                let pixel_format = choose_pixel_format(&mono, version as u32);
+                if pixel_format != nil {
+                    let () = msg_send![pixel_format, release];
+                    return Err(GlError::StereoNotSupported);
+                }
+            }
 
-        if pixel_format == nil {
             return Err(GlError::CreationFailed);
         }
 
+        let actual_config = describe_pixel_format(pixel_format, &config);
+
+        if config.matching == Matching::Exact && !config.matches_exactly(&actual_config) {
+            let () = msg_send![pixel_format, release];
+            return Err(GlError::NoExactMatch(vec![actual_config]));
+        }
+
         let view = NSOpenGLView::alloc(nil)
             .initWithFrame_pixelFormat_(parent_view.frame(), pixel_format);
 
@@ -106,7 +215,44 @@ impl GlContext {
 
         let () = msg_send![pixel_format, release];
 
-        Ok(GlContext { view, context })
+        Ok(GlContext {
+            view,
+            context,
+            config: actual_config,
+            ownership: Ownership::Owned,
+        })
+    }
+
+    pub unsafe fn from_raw(
+        handles: RawGlHandles,
+        ownership: Ownership,
+    ) -> Result<GlContext, GlError> {
+        let (view, context) = match handles {
+            RawGlHandles::Cgl {
+                ns_view,
+                ns_context,
+                ..
+            } => (ns_view as id, ns_context as id),
+            _ => return Err(GlError::InvalidWindowHandle),
+        };
+
+        if view == nil || context == nil {
+            return Err(GlError::InvalidWindowHandle);
+        }
+
+        let pixel_format: id = msg_send![context, pixelFormat];
+        let config = if pixel_format == nil {
+            GlConfig::default()
+        } else {
+            describe_pixel_format(pixel_format, &GlConfig::default())
+        };
+
+        Ok(GlContext {
+            view,
+            context,
+            config,
+            ownership,
+        })
     }
 
     pub unsafe fn make_current(&self) {
@@ -117,9 +263,43 @@ impl GlContext {
         NSOpenGLContext::clearCurrentContext(self.context);
     }
 
-    pub fn get_proc_address(&self, symbol: &str) -> *const c_void {
-        let symbol_name = CFString::from_str(symbol).unwrap();
-        let framework_name = CFString::from_str("com.apple.opengl").unwrap();
+    /// Makes the context current, returning what was current before for [`Self::restore`].
+    pub unsafe fn make_current_saving(&self) -> Previous {
+        let previous = Previous {
+            context: NSOpenGLContext::currentContext(nil),
+        };
+        self.make_current();
+        previous
+    }
+
+    pub unsafe fn restore(&self, previous: Previous) {
+        if previous.context == nil {
+            self.make_not_current();
+        } else {
+            previous.context.makeCurrentContext();
+        }
+    }
+
+    pub fn raw_handles(&self) -> RawGlHandles {
+        let cgl_context: *mut c_void = unsafe { msg_send![self.context, CGLContextObj] };
+
+        RawGlHandles::Cgl {
+            ns_view: self.view as *mut c_void,
+            ns_context: self.context as *mut c_void,
+            cgl_context,
+        }
+    }
+
+    pub fn is_current(&self) -> bool {
+        unsafe { NSOpenGLContext::currentContext(nil) == self.context }
+    }
+
+    pub fn get_proc_address_cstr(&self, symbol: &CStr) -> *const c_void {
+        let symbol_name = match symbol.to_str() {
+            Ok(symbol) => CFString::new(symbol),
+            Err(_) => return std::ptr::null(),
+        };
+        let framework_name = CFString::from_static_string("com.apple.opengl");
         let framework =
             unsafe { CFBundleGetBundleWithIdentifier(framework_name.as_concrete_TypeRef()) };
         let addr = unsafe {
@@ -134,10 +314,31 @@ impl GlContext {
             let () = msg_send![self.view, setNeedsDisplay: YES];
         }
     }
+
+    pub fn actual_config(&self) -> &GlConfig {
+        &self.config
+    }
+
+    pub fn is_direct(&self) -> bool {
+        true
+    }
+
+    pub fn glx_info(&self) -> Option<GlxInfo> {
+        None
+    }
+
+    /// CGL has no extension string.
+    pub fn platform_extensions(&self) -> &[String] {
+        &[]
+    }
 }
 
 impl Drop for GlContext {
     fn drop(&mut self) {
+        if self.ownership == Ownership::Borrowed {
+            return;
+        }
+
         unsafe {
             let () = msg_send![self.context, release];
             let () = msg_send![self.view, release];
@@ -0,0 +1,166 @@
+//! Renderer queries from GLX_MESA_query_renderer, which work on a display before any context
+//! is created.
+
+use std::ffi::{c_void, CStr};
+use std::os::raw::{c_char, c_int, c_uint};
+
+use x11::{glx, xlib};
+
+use crate::Profile;
+
+type GlXQueryRendererIntegerMESA = unsafe extern "C" fn(
+    dpy: *mut xlib::Display,
+    screen: c_int,
+    renderer: c_int,
+    attribute: c_int,
+    value: *mut c_uint,
+) -> xlib::Bool;
+
+type GlXQueryRendererStringMESA = unsafe extern "C" fn(
+    dpy: *mut xlib::Display,
+    screen: c_int,
+    renderer: c_int,
+    attribute: c_int,
+) -> *const c_char;
+
+// See https://www.khronos.org/registry/OpenGL/extensions/MESA/GLX_MESA_query_renderer.txt
+
+const GLX_RENDERER_VENDOR_ID_MESA: c_int = 0x8183;
+const GLX_RENDERER_DEVICE_ID_MESA: c_int = 0x8184;
+const GLX_RENDERER_VERSION_MESA: c_int = 0x8185;
+const GLX_RENDERER_ACCELERATED_MESA: c_int = 0x8186;
+const GLX_RENDERER_VIDEO_MEMORY_MESA: c_int = 0x8187;
+const GLX_RENDERER_UNIFIED_MEMORY_ARCHITECTURE_MESA: c_int = 0x8188;
+const GLX_RENDERER_PREFERRED_PROFILE_MESA: c_int = 0x8189;
+const GLX_RENDERER_OPENGL_CORE_PROFILE_VERSION_MESA: c_int = 0x818A;
+const GLX_RENDERER_OPENGL_COMPATIBILITY_PROFILE_VERSION_MESA: c_int = 0x818B;
+const GLX_RENDERER_OPENGL_ES_PROFILE_VERSION_MESA: c_int = 0x818C;
+
+// See https://www.khronos.org/registry/OpenGL/extensions/ARB/GLX_ARB_create_context.txt
+
+const GLX_CONTEXT_CORE_PROFILE_BIT_ARB: c_uint = 0x00000001;
+
+/// The renderer a screen's contexts would be created on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Renderer {
+    /// The PCI vendor ID, or 0xFFFFFFFF if the renderer isn't a PCI device.
+    pub vendor_id: u32,
+    /// The PCI device ID, or 0xFFFFFFFF if the renderer isn't a PCI device.
+    pub device_id: u32,
+    pub vendor: String,
+    pub device: String,
+    /// The driver version as `(major, minor, patch)`.
+    pub driver_version: (u32, u32, u32),
+    /// False for software rasterizers.
+    pub accelerated: bool,
+    /// In megabytes.
+    pub video_memory: u32,
+    /// Whether the GPU shares memory with the CPU, as integrated GPUs do.
+    pub unified_memory: bool,
+    pub preferred_profile: Profile,
+    /// `None` if core profiles aren't supported.
+    pub max_core_version: Option<(u8, u8)>,
+    /// `None` if compatibility profiles aren't supported.
+    pub max_compatibility_version: Option<(u8, u8)>,
+    /// `None` if OpenGL ES isn't supported.
+    pub max_es_version: Option<(u8, u8)>,
+}
+
+impl Renderer {
+    /// Queries the renderer of `screen` on `display`, an Xlib `Display*`. Returns `None` if
+    /// the GLX implementation doesn't support GLX_MESA_query_renderer.
+    ///
+    /// # Safety
+    ///
+    /// `display` must be an open Xlib display, and `screen` one of its screens.
+    pub unsafe fn query(display: *mut c_void, screen: i32) -> Option<Renderer> {
+        let display = display as *mut xlib::Display;
+
+        let extensions = glx::glXQueryExtensionsString(display, screen);
+        if extensions.is_null()
+            || !CStr::from_ptr(extensions)
+                .to_string_lossy()
+                .split_whitespace()
+                .any(|e| e == "GLX_MESA_query_renderer")
+        {
+            return None;
+        }
+
+        #[allow(non_snake_case)]
+        let (glXQueryRendererIntegerMESA, glXQueryRendererStringMESA) = {
+            let integer = get_proc_address(b"glXQueryRendererIntegerMESA\0")?;
+            let string = get_proc_address(b"glXQueryRendererStringMESA\0")?;
+            (
+                std::mem::transmute::<*const c_void, GlXQueryRendererIntegerMESA>(integer),
+                std::mem::transmute::<*const c_void, GlXQueryRendererStringMESA>(string),
+            )
+        };
+
+        // Only renderer 0 is ever reported, since Mesa has one renderer per screen.
+        let integers = |attribute, values: &mut [c_uint]| {
+            glXQueryRendererIntegerMESA(display, screen, 0, attribute, values.as_mut_ptr()) != 0
+        };
+        let integer = |attribute| {
+            let mut value = [0];
+            if integers(attribute, &mut value) {
+                Some(value[0])
+            } else {
+                None
+            }
+        };
+        let string = |attribute| {
+            let string = glXQueryRendererStringMESA(display, screen, 0, attribute);
+            if string.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(string).to_string_lossy().into_owned()
+            }
+        };
+        let version = |attribute| {
+            let mut version = [0; 2];
+            if !integers(attribute, &mut version) || version == [0, 0] {
+                return None;
+            }
+            Some((version[0] as u8, version[1] as u8))
+        };
+
+        let mut driver_version = [0; 3];
+        integers(GLX_RENDERER_VERSION_MESA, &mut driver_version);
+
+        Some(Renderer {
+            vendor_id: integer(GLX_RENDERER_VENDOR_ID_MESA)?,
+            device_id: integer(GLX_RENDERER_DEVICE_ID_MESA)?,
+            vendor: string(GLX_RENDERER_VENDOR_ID_MESA),
+            device: string(GLX_RENDERER_DEVICE_ID_MESA),
+            driver_version: (driver_version[0], driver_version[1], driver_version[2]),
+            accelerated: integer(GLX_RENDERER_ACCELERATED_MESA).unwrap_or(0) != 0,
+            video_memory: integer(GLX_RENDERER_VIDEO_MEMORY_MESA).unwrap_or(0),
+            unified_memory: integer(GLX_RENDERER_UNIFIED_MEMORY_ARCHITECTURE_MESA).unwrap_or(0)
+                != 0,
+            preferred_profile: match integer(GLX_RENDERER_PREFERRED_PROFILE_MESA) {
+                Some(GLX_CONTEXT_CORE_PROFILE_BIT_ARB) => Profile::Core,
+                _ => Profile::Compatibility,
+            },
+            max_core_version: version(GLX_RENDERER_OPENGL_CORE_PROFILE_VERSION_MESA),
+            max_compatibility_version: version(
+                GLX_RENDERER_OPENGL_COMPATIBILITY_PROFILE_VERSION_MESA,
+            ),
+            max_es_version: version(GLX_RENDERER_OPENGL_ES_PROFILE_VERSION_MESA),
+        })
+    }
+
+    /// The highest desktop GL version supported with `profile`, for [`GlConfig::version`].
+    ///
+    /// [`GlConfig::version`]: crate::GlConfig::version
+    pub fn max_version(&self, profile: Profile) -> Option<(u8, u8)> {
+        match profile {
+            Profile::Core => self.max_core_version,
+            Profile::Compatibility => self.max_compatibility_version,
+        }
+    }
+}
+
+fn get_proc_address(symbol: &[u8]) -> Option<*const c_void> {
+    unsafe { glx::glXGetProcAddress(symbol.as_ptr()) }.map(|addr| addr as *const c_void)
+}
@@ -0,0 +1,258 @@
+//! A backend that creates no real contexts, for testing code that uses [`GlContext`] without a
+//! display. Enabled with the `mock` feature, and used for contexts whose [`GlConfig::backend`]
+//! is [`Backend::Mock`], e.g. with `RAW_GL_BACKEND=mock`, and for [`RawGlHandles::Mock`]. The
+//! platform backend stays available alongside it.
+//!
+//! Every call is recorded in a per-thread log, read with [`take_calls`]. Failures can be
+//! scripted with [`fail_next_create`]. The rules a driver enforces are checked, and breaking
+//! one panics:
+//!
+//! - `swap_buffers` requires the context to be current on the calling thread.
+//! - A context can only be current on one thread at a time.
+//!
+//! [`GlContext`]: crate::GlContext
+//! [`GlConfig::backend`]: crate::GlConfig::backend
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{c_void, CStr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+
+use raw_window_handle::RawWindowHandle;
+
+use crate::{Backend, GlConfig, GlError, GlxInfo, Ownership, RawGlHandles};
+
+pub const BACKEND: Backend = Backend::Mock;
+
+/// A call made on the mock backend.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Call {
+    /// A context was created. `id` is `None` if creation failed.
+    Create {
+        id: Option<usize>,
+    },
+    FromRaw {
+        id: usize,
+    },
+    MakeCurrent {
+        id: usize,
+    },
+    MakeNotCurrent {
+        id: usize,
+    },
+    SwapBuffers {
+        id: usize,
+    },
+    GetProcAddress {
+        id: usize,
+        symbol: String,
+    },
+    Drop {
+        id: usize,
+    },
+}
+
+type Failure = Box<dyn FnOnce() -> GlError>;
+
+thread_local! {
+    static CALLS: RefCell<Vec<Call>> = const { RefCell::new(Vec::new()) };
+    static CREATE_FAILURES: RefCell<VecDeque<Failure>> = RefCell::new(VecDeque::new());
+    static PROC_ADDRESSES: RefCell<HashMap<String, usize>> = RefCell::new(HashMap::new());
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// The thread each context is current on. Contexts are shared between threads, so unlike the
+/// call log this is global.
+static CURRENT: Mutex<Option<HashMap<usize, ThreadId>>> = Mutex::new(None);
+
+fn record(call: Call) {
+    CALLS.with(|calls| calls.borrow_mut().push(call));
+}
+
+fn with_current<R>(f: impl FnOnce(&mut HashMap<usize, ThreadId>) -> R) -> R {
+    let mut current = CURRENT.lock().unwrap_or_else(|err| err.into_inner());
+    f(current.get_or_insert_with(HashMap::new))
+}
+
+fn make_current(id: usize) {
+    record(Call::MakeCurrent { id });
+
+    let this_thread = thread::current().id();
+    with_current(|current| {
+        if let Some(&thread) = current.get(&id) {
+            assert!(
+                thread == this_thread,
+                "context {} made current while current on another thread",
+                id
+            );
+        }
+
+        // Making a context current releases the one previously current on this thread.
+        current.retain(|_, thread| *thread != this_thread);
+        current.insert(id, this_thread);
+    });
+}
+
+/// Returns and clears the calls made on this thread.
+pub fn take_calls() -> Vec<Call> {
+    CALLS.with(|calls| std::mem::take(&mut *calls.borrow_mut()))
+}
+
+/// Makes the next context creation on this thread fail with the error returned by `error`.
+/// Failures queue up, so calling this twice fails the next two creations.
+pub fn fail_next_create(error: impl FnOnce() -> GlError + 'static) {
+    CREATE_FAILURES.with(|failures| failures.borrow_mut().push_back(Box::new(error)));
+}
+
+/// Makes `get_proc_address` return `address` for `symbol` on this thread. Unknown symbols
+/// return null.
+pub fn set_proc_address(symbol: &str, address: *const c_void) {
+    PROC_ADDRESSES.with(|addresses| {
+        addresses
+            .borrow_mut()
+            .insert(symbol.to_string(), address as usize)
+    });
+}
+
+/// Clears the call log, scripted failures and proc addresses of this thread.
+pub fn reset() {
+    take_calls();
+    CREATE_FAILURES.with(|failures| failures.borrow_mut().clear());
+    PROC_ADDRESSES.with(|addresses| addresses.borrow_mut().clear());
+}
+
+/// The context current on a thread, saved to be restored later.
+pub(crate) struct Previous {
+    id: Option<usize>,
+}
+
+pub(crate) struct GlContext {
+    id: usize,
+    config: GlConfig,
+    ownership: Ownership,
+}
+
+impl GlContext {
+    pub unsafe fn create(_handle: RawWindowHandle, config: GlConfig) -> Result<GlContext, GlError> {
+        if let Some(failure) = CREATE_FAILURES.with(|failures| failures.borrow_mut().pop_front()) {
+            record(Call::Create { id: None });
+            return Err(failure());
+        }
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        record(Call::Create { id: Some(id) });
+
+        Ok(GlContext {
+            id,
+            config,
+            ownership: Ownership::Owned,
+        })
+    }
+
+    pub unsafe fn from_raw(
+        handles: RawGlHandles,
+        ownership: Ownership,
+    ) -> Result<GlContext, GlError> {
+        let id = match handles {
+            RawGlHandles::Mock { id } => id,
+            _ => return Err(GlError::InvalidWindowHandle),
+        };
+        record(Call::FromRaw { id });
+
+        Ok(GlContext {
+            id,
+            config: GlConfig::default(),
+            ownership,
+        })
+    }
+
+    pub unsafe fn make_current(&self) {
+        make_current(self.id);
+    }
+
+    /// Makes the context current, returning what was current before for [`Self::restore`].
+    pub unsafe fn make_current_saving(&self) -> Previous {
+        let this_thread = thread::current().id();
+        let previous = with_current(|current| {
+            current
+                .iter()
+                .find(|(_, thread)| **thread == this_thread)
+                .map(|(&id, _)| id)
+        });
+        self.make_current();
+        Previous { id: previous }
+    }
+
+    pub unsafe fn restore(&self, previous: Previous) {
+        match previous.id {
+            Some(id) => make_current(id),
+            None => self.make_not_current(),
+        }
+    }
+
+    pub unsafe fn make_not_current(&self) {
+        record(Call::MakeNotCurrent { id: self.id });
+
+        let this_thread = thread::current().id();
+        with_current(|current| current.retain(|_, thread| *thread != this_thread));
+    }
+
+    pub fn get_proc_address_cstr(&self, symbol: &CStr) -> *const c_void {
+        let symbol = symbol.to_string_lossy().into_owned();
+        let address = PROC_ADDRESSES.with(|addresses| addresses.borrow().get(&symbol).copied());
+        record(Call::GetProcAddress {
+            id: self.id,
+            symbol,
+        });
+
+        address.unwrap_or(0) as *const c_void
+    }
+
+    pub fn swap_buffers(&self) {
+        record(Call::SwapBuffers { id: self.id });
+
+        assert!(
+            self.is_current(),
+            "swap_buffers on context {} while it isn't current",
+            self.id
+        );
+    }
+
+    pub fn actual_config(&self) -> &GlConfig {
+        &self.config
+    }
+
+    pub fn is_direct(&self) -> bool {
+        true
+    }
+
+    pub fn glx_info(&self) -> Option<GlxInfo> {
+        None
+    }
+
+    pub fn platform_extensions(&self) -> &[String] {
+        &[]
+    }
+
+    pub fn raw_handles(&self) -> RawGlHandles {
+        RawGlHandles::Mock { id: self.id }
+    }
+
+    pub fn is_current(&self) -> bool {
+        let this_thread = thread::current().id();
+        with_current(|current| current.get(&self.id) == Some(&this_thread))
+    }
+}
+
+impl Drop for GlContext {
+    fn drop(&mut self) {
+        record(Call::Drop { id: self.id });
+
+        if self.ownership == Ownership::Owned {
+            with_current(|current| current.remove(&self.id));
+        }
+    }
+}
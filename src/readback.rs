@@ -0,0 +1,239 @@
+use crate::gl;
+use crate::{GlContext, GlError};
+
+/// A rectangle of the framebuffer, in pixels from the bottom left corner as in `glReadPixels`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// The `width` by `height` region at the origin.
+    pub fn full(width: u32, height: u32) -> Region {
+        Region {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb8,
+    Rgba8,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Rgba8 => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadBuffer {
+    Back,
+    Front,
+}
+
+/// How the values in an [`Image`] are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Linear,
+    /// The framebuffer is sRGB-capable, so the values are assumed to be sRGB-encoded.
+    Srgb,
+}
+
+/// Pixels read back from a framebuffer, tightly packed, with rows from top to bottom.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub color_space: ColorSpace,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    #[cfg(feature = "image")]
+    pub fn save_png(&self, path: impl AsRef<std::path::Path>) -> image::ImageResult<()> {
+        let color_type = match self.format {
+            PixelFormat::Rgb8 => image::ExtendedColorType::Rgb8,
+            PixelFormat::Rgba8 => image::ExtendedColorType::Rgba8,
+        };
+
+        image::save_buffer_with_format(
+            path,
+            &self.data,
+            self.width,
+            self.height,
+            color_type,
+            image::ImageFormat::Png,
+        )
+    }
+}
+
+impl GlContext {
+    /// Reads `region` of the back buffer, or of the front buffer for single-buffered contexts.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current.
+    pub unsafe fn read_pixels(
+        &self,
+        region: Region,
+        format: PixelFormat,
+    ) -> Result<Image, GlError> {
+        let buffer = if self.config.double_buffer {
+            ReadBuffer::Back
+        } else {
+            ReadBuffer::Front
+        };
+
+        self.read_pixels_from(buffer, region, format)
+    }
+
+    /// Reads `region` of the default framebuffer's `buffer`. Any state changed for the read
+    /// (the read framebuffer and its read buffer, the pixel pack buffer and pack parameters,
+    /// `GL_FRAMEBUFFER_SRGB`) is restored.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current.
+    pub unsafe fn read_pixels_from(
+        &self,
+        buffer: ReadBuffer,
+        region: Region,
+        format: PixelFormat,
+    ) -> Result<Image, GlError> {
+        let gl = &self.gl;
+        let read_pixels = gl
+            .read_pixels
+            .ok_or(GlError::FunctionNotAvailable("glReadPixels"))?;
+
+        // The read buffer belongs to the framebuffer, so the default framebuffer is bound
+        // before its read buffer is saved, and the read buffer is restored before the previous
+        // framebuffer is bound again.
+        let read_framebuffer = match (self.config.version >= (3, 0), gl.bind_framebuffer) {
+            (true, Some(bind_framebuffer)) => {
+                let framebuffer = gl.get_integer(gl::READ_FRAMEBUFFER_BINDING);
+                bind_framebuffer(gl::READ_FRAMEBUFFER, 0);
+                framebuffer
+            }
+            _ => None,
+        };
+        let read_buffer = gl.get_integer(gl::READ_BUFFER);
+        if let Some(read_buffer) = gl.read_buffer {
+            read_buffer(match buffer {
+                ReadBuffer::Back => gl::BACK,
+                ReadBuffer::Front => gl::FRONT,
+            });
+        }
+
+        // With a pixel pack buffer bound, glReadPixels would write to the buffer instead.
+        let pack_buffer = match (self.config.version >= (2, 1), gl.bind_buffer) {
+            (true, Some(bind_buffer)) => {
+                let buffer = gl.get_integer(gl::PIXEL_PACK_BUFFER_BINDING);
+                bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+                buffer
+            }
+            _ => None,
+        };
+        let pack_state: Vec<(gl::GLenum, Option<gl::GLint>)> = [
+            (gl::PACK_ALIGNMENT, 1),
+            (gl::PACK_ROW_LENGTH, 0),
+            (gl::PACK_SKIP_ROWS, 0),
+            (gl::PACK_SKIP_PIXELS, 0),
+        ]
+        .iter()
+        .map(|&(name, value)| {
+            let saved = gl.get_integer(name);
+            if let Some(pixel_storei) = gl.pixel_storei {
+                pixel_storei(name, value);
+            }
+            (name, saved)
+        })
+        .collect();
+
+        // With GL_FRAMEBUFFER_SRGB enabled, some drivers convert sRGB values to linear on
+        // read, so it's disabled to get the stored values.
+        let framebuffer_srgb = self.config.srgb
+            && gl
+                .is_enabled
+                .is_some_and(|is_enabled| is_enabled(gl::FRAMEBUFFER_SRGB) != 0);
+        if let (true, Some(disable)) = (framebuffer_srgb, gl.disable) {
+            disable(gl::FRAMEBUFFER_SRGB);
+        }
+
+        let (gl_format, bytes_per_pixel) = match format {
+            PixelFormat::Rgb8 => (gl::RGB, 3),
+            PixelFormat::Rgba8 => (gl::RGBA, 4),
+        };
+        let stride = region.width as usize * bytes_per_pixel;
+        let mut data = vec![0u8; stride * region.height as usize];
+        read_pixels(
+            region.x,
+            region.y,
+            region.width as i32,
+            region.height as i32,
+            gl_format,
+            gl::UNSIGNED_BYTE,
+            data.as_mut_ptr() as *mut _,
+        );
+
+        if let (true, Some(enable)) = (framebuffer_srgb, gl.enable) {
+            enable(gl::FRAMEBUFFER_SRGB);
+        }
+        if let Some(pixel_storei) = gl.pixel_storei {
+            for &(name, value) in &pack_state {
+                if let Some(value) = value {
+                    pixel_storei(name, value);
+                }
+            }
+        }
+        if let (Some(buffer), Some(bind_buffer)) = (pack_buffer, gl.bind_buffer) {
+            bind_buffer(gl::PIXEL_PACK_BUFFER, buffer as gl::GLuint);
+        }
+        if let (Some(buffer), Some(read_buffer)) = (read_buffer, gl.read_buffer) {
+            read_buffer(buffer as gl::GLenum);
+        }
+        if let (Some(framebuffer), Some(bind_framebuffer)) = (read_framebuffer, gl.bind_framebuffer)
+        {
+            bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer as gl::GLuint);
+        }
+
+        // glReadPixels returns rows from bottom to top.
+        flip_rows(&mut data, stride);
+
+        Ok(Image {
+            width: region.width,
+            height: region.height,
+            format,
+            color_space: if self.config.srgb {
+                ColorSpace::Srgb
+            } else {
+                ColorSpace::Linear
+            },
+            data,
+        })
+    }
+}
+
+fn flip_rows(data: &mut [u8], stride: usize) {
+    if stride == 0 {
+        return;
+    }
+
+    let rows = data.len() / stride;
+    for row in 0..rows / 2 {
+        let (top, bottom) = data.split_at_mut((rows - row - 1) * stride);
+        top[row * stride..(row + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+    }
+}
@@ -0,0 +1,481 @@
+//! Reading and replaying traces recorded with [`GlConfig::record_trace`].
+//!
+//! A trace starts with a header: the magic `RGLTRACE`, the format version as a little-endian
+//! `u32`, the context's major and minor version and whether it has a core profile as bytes,
+//! and the viewport width and height when the context was first made current as
+//! little-endian `u32`s. Records follow, each starting with a byte:
+//!
+//! - `0` names a command: its id, then the length and bytes of its name.
+//! - `1` is a call: the command id, the number of arguments, each argument, then the return
+//!   value.
+//! - `2` is a call to [`GlContext::swap_buffers`].
+//!
+//! Each argument starts with a byte: `0` for a value, `1` for the length and bytes of the data
+//! it pointed to, `2` for the length of a buffer the call wrote to, `3` for a number of
+//! strings followed by the length and bytes of each, and `4` for a buffer the call wrote to
+//! whose length couldn't be worked out, such as glGetTexImage's. Calls with an argument of the
+//! last kind are skipped on replay. Integers other than those in the header are unsigned
+//! LEB128.
+//!
+//! Object names, uniform locations and sync objects are replayed as they were recorded,
+//! which relies on the driver handing out the same names again. Writes through mapped
+//! buffers aren't recorded, and pointers whose length can't be worked out from gl.xml, such
+//! as client-side vertex arrays, are recorded as addresses.
+//!
+//! [`GlConfig::record_trace`]: crate::GlConfig::record_trace
+
+use std::collections::HashMap;
+use std::ffi::{c_void, CString};
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::os::raw::c_char;
+use std::path::Path;
+
+use crate::functions::{find_command, replay_call, COMMANDS, PARAMS};
+use crate::{GlContext, Profile};
+
+pub(crate) const MAGIC: &[u8; 8] = b"RGLTRACE";
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+pub(crate) const RECORD_DEFINE: u8 = 0;
+pub(crate) const RECORD_CALL: u8 = 1;
+pub(crate) const RECORD_SWAP: u8 = 2;
+
+pub(crate) const ARG_VALUE: u8 = 0;
+pub(crate) const ARG_BLOB: u8 = 1;
+pub(crate) const ARG_SCRATCH: u8 = 2;
+pub(crate) const ARG_STRINGS: u8 = 3;
+pub(crate) const ARG_UNSIZED: u8 = 4;
+
+/// The largest scratch buffer a trace can ask for, enough for an 8192x8192 image of four
+/// floats. Anything larger is taken as corruption rather than allocated.
+const MAX_SCRATCH_LEN: u64 = 1 << 30;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// The file doesn't start with a trace header.
+    NotATrace,
+    /// The trace was written by a newer version of this crate.
+    UnsupportedVersion(u32),
+    /// The trace ends in the middle of a record, or contains a record this crate can't read.
+    Corrupt,
+    /// The trace calls a command this build doesn't know.
+    UnknownCommand(String),
+    /// The replaying context doesn't provide a command the trace calls.
+    MissingFunction(&'static str),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "{}", err),
+            ReplayError::NotATrace => write!(f, "not a GL trace"),
+            ReplayError::UnsupportedVersion(version) => {
+                write!(f, "unsupported trace format version {}", version)
+            }
+            ReplayError::Corrupt => write!(f, "the trace is truncated or corrupt"),
+            ReplayError::UnknownCommand(name) => write!(f, "unknown command {}", name),
+            ReplayError::MissingFunction(name) => write!(f, "{} isn't available", name),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> ReplayError {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            ReplayError::Corrupt
+        } else {
+            ReplayError::Io(err)
+        }
+    }
+}
+
+enum Record {
+    Call { command: usize, args: Vec<Arg> },
+    Swap,
+}
+
+enum Arg {
+    Value(u64),
+    /// Stored as `u64`s so that the data is aligned for any element type.
+    Blob(Vec<u64>),
+    Scratch(usize),
+    Strings(Vec<CString>),
+    Unsized,
+}
+
+/// A trace loaded into memory.
+pub struct Trace {
+    version: (u8, u8),
+    profile: Profile,
+    viewport: (u32, u32),
+    records: Vec<Record>,
+}
+
+impl Trace {
+    pub fn open(path: impl AsRef<Path>) -> Result<Trace, ReplayError> {
+        let file = std::fs::File::open(path).map_err(ReplayError::Io)?;
+        Trace::read(&mut BufReader::new(file))
+    }
+
+    pub fn read(reader: &mut impl Read) -> Result<Trace, ReplayError> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ReplayError::NotATrace);
+        }
+
+        let mut header = [0; 15];
+        reader.read_exact(&mut header)?;
+        let u32_at =
+            |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        let format_version = u32_at(0);
+        if format_version > FORMAT_VERSION {
+            return Err(ReplayError::UnsupportedVersion(format_version));
+        }
+
+        let mut trace = Trace {
+            version: (header[4], header[5]),
+            profile: if header[6] != 0 {
+                Profile::Core
+            } else {
+                Profile::Compatibility
+            },
+            viewport: (u32_at(7), u32_at(11)),
+            records: Vec::new(),
+        };
+
+        // Ids are the recording build's command indices, which may differ from this build's.
+        let mut commands = HashMap::new();
+        loop {
+            let mut tag = [0];
+            if reader.read(&mut tag)? == 0 {
+                break;
+            }
+
+            match tag[0] {
+                RECORD_DEFINE => {
+                    let id = read_varint(reader)?;
+                    let name =
+                        String::from_utf8(read_bytes(reader)?).map_err(|_| ReplayError::Corrupt)?;
+                    match find_command(&name) {
+                        Some(command) => commands.insert(id, command),
+                        None => return Err(ReplayError::UnknownCommand(name)),
+                    };
+                }
+                RECORD_CALL => {
+                    let command = *commands
+                        .get(&read_varint(reader)?)
+                        .ok_or(ReplayError::Corrupt)?;
+                    let count = read_varint(reader)?;
+                    let args = (0..count)
+                        .map(|_| read_arg(reader))
+                        .collect::<Result<Vec<Arg>, ReplayError>>()?;
+                    if args.len() != PARAMS[command].len() {
+                        return Err(ReplayError::Corrupt);
+                    }
+                    // The return value is only recorded for inspecting traces.
+                    read_varint(reader)?;
+                    trace.records.push(Record::Call { command, args });
+                }
+                RECORD_SWAP => trace.records.push(Record::Swap),
+                _ => return Err(ReplayError::Corrupt),
+            }
+        }
+
+        Ok(trace)
+    }
+
+    /// The version of the recorded context.
+    pub fn version(&self) -> (u8, u8) {
+        self.version
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    /// The viewport size when the recorded context was first made current, which is normally
+    /// the size of its window.
+    pub fn viewport(&self) -> (u32, u32) {
+        self.viewport
+    }
+
+    /// The number of `swap_buffers` calls in the trace.
+    pub fn frames(&self) -> usize {
+        self.records
+            .iter()
+            .filter(|record| matches!(record, Record::Swap))
+            .count()
+    }
+
+    /// Replays the trace on `context`, which must be current. `on_swap` is called with the
+    /// frame number in place of each recorded `swap_buffers`, and is responsible for swapping.
+    ///
+    /// # Safety
+    ///
+    /// `context` must be current. The trace's calls are made as recorded, so a trace from an
+    /// untrusted source can do anything a GL call with bad arguments can, such as read
+    /// through pointers recorded as addresses.
+    pub unsafe fn replay(
+        &self,
+        context: &GlContext,
+        mut on_swap: impl FnMut(usize),
+    ) -> Result<(), ReplayError> {
+        let mut addresses: HashMap<usize, *const c_void> = HashMap::new();
+        let mut frame = 0;
+
+        for record in &self.records {
+            let (command, args) = match record {
+                Record::Call { args, .. } if args.iter().any(|arg| matches!(arg, Arg::Unsized)) => {
+                    continue;
+                }
+                Record::Call { command, args } => (*command, args),
+                Record::Swap => {
+                    on_swap(frame);
+                    frame += 1;
+                    continue;
+                }
+            };
+
+            let name = COMMANDS[command].name;
+            let addr = *addresses
+                .entry(command)
+                .or_insert_with(|| context.context_proc_address(name));
+            if addr.is_null() {
+                return Err(ReplayError::MissingFunction(name));
+            }
+
+            // Scratch buffers and string arrays only need to live until the call returns.
+            let mut scratch: Vec<Vec<u64>> = Vec::new();
+            let mut strings: Vec<Vec<*const c_char>> = Vec::new();
+            let values: Vec<u64> = args
+                .iter()
+                .map(|arg| match arg {
+                    Arg::Value(value) => *value,
+                    Arg::Blob(data) => data.as_ptr() as usize as u64,
+                    Arg::Scratch(len) => {
+                        scratch.push(vec![0; len.div_ceil(8)]);
+                        scratch.last_mut().unwrap().as_mut_ptr() as usize as u64
+                    }
+                    Arg::Strings(list) => {
+                        strings.push(list.iter().map(|string| string.as_ptr()).collect());
+                        strings.last().unwrap().as_ptr() as usize as u64
+                    }
+                    Arg::Unsized => unreachable!(),
+                })
+                .collect();
+
+            replay_call(command, addr, &values);
+        }
+
+        Ok(())
+    }
+}
+
+fn read_arg(reader: &mut impl Read) -> Result<Arg, ReplayError> {
+    let mut tag = [0];
+    reader.read_exact(&mut tag)?;
+
+    let arg = match tag[0] {
+        ARG_VALUE => Arg::Value(read_varint(reader)?),
+        ARG_BLOB => {
+            let data = read_bytes(reader)?
+                .chunks(8)
+                .map(|chunk| {
+                    let mut word = [0; 8];
+                    word[..chunk.len()].copy_from_slice(chunk);
+                    u64::from_ne_bytes(word)
+                })
+                .collect();
+            Arg::Blob(data)
+        }
+        ARG_SCRATCH => match read_varint(reader)? {
+            len if len <= MAX_SCRATCH_LEN => Arg::Scratch(len as usize),
+            _ => return Err(ReplayError::Corrupt),
+        },
+        ARG_STRINGS => {
+            let count = read_varint(reader)?;
+            let strings = (0..count)
+                .map(|_| {
+                    let mut bytes = read_bytes(reader)?;
+                    // Strings with explicit lengths may contain NULs, which GL then ignores.
+                    bytes.retain(|&byte| byte != 0);
+                    Ok(CString::new(bytes).unwrap())
+                })
+                .collect::<Result<Vec<CString>, ReplayError>>()?;
+            Arg::Strings(strings)
+        }
+        ARG_UNSIZED => Arg::Unsized,
+        _ => return Err(ReplayError::Corrupt),
+    };
+
+    Ok(arg)
+}
+
+fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>, ReplayError> {
+    let len = read_varint(reader)? as usize;
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(ReplayError::Corrupt);
+    }
+
+    Ok(bytes)
+}
+
+pub(crate) fn write_varint(writer: &mut impl Write, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<u64, ReplayError> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(ReplayError::Corrupt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&[3, 3, 1]);
+        bytes.extend_from_slice(&64u32.to_le_bytes());
+        bytes.extend_from_slice(&48u32.to_le_bytes());
+        bytes
+    }
+
+    /// A header, a definition of glClear as command 7, then `records`.
+    fn trace(records: &[u8]) -> Vec<u8> {
+        let mut bytes = header();
+        bytes.push(RECORD_DEFINE);
+        write_varint(&mut bytes, 7).unwrap();
+        write_varint(&mut bytes, 7).unwrap();
+        bytes.extend_from_slice(b"glClear");
+        bytes.extend_from_slice(records);
+        bytes
+    }
+
+    fn read(bytes: &[u8]) -> Result<Trace, ReplayError> {
+        Trace::read(&mut &bytes[..])
+    }
+
+    #[test]
+    fn valid() {
+        let trace = read(&trace(&[
+            RECORD_CALL,
+            7,
+            1,
+            ARG_VALUE,
+            0x80,
+            0x80,
+            0x01,
+            0,
+            RECORD_SWAP,
+        ]))
+        .unwrap();
+        assert_eq!(trace.version(), (3, 3));
+        assert_eq!(trace.profile(), Profile::Core);
+        assert_eq!(trace.viewport(), (64, 48));
+        assert_eq!(trace.frames(), 1);
+        assert!(matches!(
+            &trace.records[0],
+            Record::Call { args, .. } if matches!(args[..], [Arg::Value(0x4000)])
+        ));
+    }
+
+    #[test]
+    fn bad_header() {
+        assert!(matches!(read(b""), Err(ReplayError::Corrupt)));
+        assert!(matches!(
+            read(b"PNG\r\n\x1a\n\0"),
+            Err(ReplayError::NotATrace)
+        ));
+        assert!(matches!(read(&header()[..12]), Err(ReplayError::Corrupt)));
+
+        let mut bytes = header();
+        bytes[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            read(&bytes),
+            Err(ReplayError::UnsupportedVersion(version)) if version == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = trace(&[RECORD_CALL, 7, 1, ARG_VALUE, 0x80, 0x80, 0x01, 0]);
+        // Cutting the trace between records leaves a valid trace.
+        let lens = (header().len() + 1..bytes.len()).filter(|&len| len != trace(&[]).len());
+        for len in lens {
+            assert!(
+                matches!(read(&bytes[..len]), Err(ReplayError::Corrupt)),
+                "{} bytes",
+                len
+            );
+        }
+
+        let blob = trace(&[RECORD_CALL, 7, 1, ARG_BLOB, 4, 1, 2]);
+        assert!(matches!(read(&blob), Err(ReplayError::Corrupt)));
+    }
+
+    #[test]
+    fn corrupt_records() {
+        // An unknown record tag.
+        assert!(matches!(read(&trace(&[9])), Err(ReplayError::Corrupt)));
+        // A call to a command that wasn't defined.
+        let undefined = trace(&[RECORD_CALL, 8, 1, ARG_VALUE, 0, 0]);
+        assert!(matches!(read(&undefined), Err(ReplayError::Corrupt)));
+        // glClear takes one argument.
+        let arguments = trace(&[RECORD_CALL, 7, 2, ARG_VALUE, 0, ARG_VALUE, 0, 0]);
+        assert!(matches!(read(&arguments), Err(ReplayError::Corrupt)));
+        // An unknown argument tag.
+        let argument = trace(&[RECORD_CALL, 7, 1, 9, 0, 0]);
+        assert!(matches!(read(&argument), Err(ReplayError::Corrupt)));
+        // A scratch buffer too large to allocate.
+        let mut scratch = trace(&[RECORD_CALL, 7, 1, ARG_SCRATCH]);
+        write_varint(&mut scratch, u64::MAX).unwrap();
+        scratch.push(0);
+        assert!(matches!(read(&scratch), Err(ReplayError::Corrupt)));
+        // A varint longer than 64 bits.
+        let varint = trace(&[RECORD_CALL, 7, 1, ARG_VALUE, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert!(matches!(
+            read(&[&varint[..], &[0xff; 6]].concat()),
+            Err(ReplayError::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn unknown_command() {
+        let mut bytes = header();
+        bytes.extend_from_slice(&[RECORD_DEFINE, 0, 6]);
+        bytes.extend_from_slice(b"glNope");
+        assert!(matches!(
+            read(&bytes),
+            Err(ReplayError::UnknownCommand(name)) if name == "glNope"
+        ));
+
+        let mut bytes = header();
+        bytes.extend_from_slice(&[RECORD_DEFINE, 0, 2, 0xff, 0xfe]);
+        assert!(matches!(read(&bytes), Err(ReplayError::Corrupt)));
+    }
+}
@@ -0,0 +1,483 @@
+//! Golden-image tests for GL rendering under Xvfb with Mesa's software renderer.
+//!
+//! ```ignore
+//! let display = Display::open()?;
+//! GoldenTest::new(64, 64).run(&display, "tests/golden/clear.png", |context| unsafe {
+//!     let gl = context.load_functions(&[]).unwrap();
+//!     gl.ClearColor(1.0, 0.0, 0.0, 1.0);
+//!     gl.Clear(gl::COLOR_BUFFER_BIT);
+//! })?;
+//! ```
+//!
+//! Setting `RAW_GL_BLESS=1` writes the rendered images as the new golden images instead of
+//! comparing against them.
+//!
+//! For the images not to depend on the GPU, run the tests with Mesa's llvmpipe by setting
+//! `LIBGL_ALWAYS_SOFTWARE=1` and `GALLIUM_DRIVER=llvmpipe` in their environment. Mesa reads
+//! these when the first context is created, so they can't be set from the tests themselves.
+
+use std::ffi::CString;
+use std::os::raw::c_ulong;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use raw_window_handle::unix::XlibHandle;
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+
+use x11::xlib;
+
+use crate::{
+    ColorFormat, DirectRendering, GlConfig, GlContext, GlError, Image, Matching, PixelFormat,
+    Profile, Region,
+};
+
+const XVFB_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum TestError {
+    /// No display could be connected to or started.
+    NoDisplay,
+    Xvfb(std::io::Error),
+    WindowCreationFailed,
+    Context(GlError),
+    Image(image::ImageError),
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// Pixels differed by more than the tolerance. `actual` and `diff` are the paths the
+    /// rendered image and the difference image were written to.
+    Mismatch {
+        pixels: usize,
+        max_difference: u8,
+        actual: PathBuf,
+        diff: PathBuf,
+    },
+}
+
+impl From<GlError> for TestError {
+    fn from(err: GlError) -> TestError {
+        TestError::Context(err)
+    }
+}
+
+impl From<image::ImageError> for TestError {
+    fn from(err: image::ImageError) -> TestError {
+        TestError::Image(err)
+    }
+}
+
+/// A connection to an X server, started with Xvfb if `DISPLAY` isn't set or can't be
+/// connected to.
+pub struct Display {
+    display: *mut xlib::Display,
+    xvfb: Option<Child>,
+}
+
+impl Display {
+    pub fn open() -> Result<Display, TestError> {
+        if std::env::var_os("DISPLAY").is_some() {
+            let display = unsafe { xlib::XOpenDisplay(std::ptr::null()) };
+            if !display.is_null() {
+                return Ok(Display {
+                    display,
+                    xvfb: None,
+                });
+            }
+        }
+
+        Self::start_xvfb()
+    }
+
+    /// Starts Xvfb on the first free display number from :99 and connects to it.
+    pub fn start_xvfb() -> Result<Display, TestError> {
+        let number = (99..200)
+            .find(|n| !Path::new(&format!("/tmp/.X{}-lock", n)).exists())
+            .ok_or(TestError::NoDisplay)?;
+        let name = format!(":{}", number);
+
+        let mut xvfb = Command::new("Xvfb")
+            .args([&name, "-screen", "0", "1024x768x24", "-nolisten", "tcp"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(TestError::Xvfb)?;
+
+        let c_name = CString::new(name).unwrap();
+        let start = Instant::now();
+        while start.elapsed() < XVFB_TIMEOUT {
+            let display = unsafe { xlib::XOpenDisplay(c_name.as_ptr()) };
+            if !display.is_null() {
+                return Ok(Display {
+                    display,
+                    xvfb: Some(xvfb),
+                });
+            }
+
+            if let Ok(Some(_)) = xvfb.try_wait() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        let _ = xvfb.kill();
+        let _ = xvfb.wait();
+        Err(TestError::NoDisplay)
+    }
+
+    pub fn as_ptr(&self) -> *mut xlib::Display {
+        self.display
+    }
+}
+
+impl Drop for Display {
+    fn drop(&mut self) {
+        unsafe {
+            xlib::XCloseDisplay(self.display);
+        }
+
+        if let Some(xvfb) = &mut self.xvfb {
+            let _ = xvfb.kill();
+            let _ = xvfb.wait();
+        }
+    }
+}
+
+/// A mapped X window to render into.
+pub struct TestWindow<'a> {
+    display: &'a Display,
+    window: c_ulong,
+}
+
+impl<'a> TestWindow<'a> {
+    pub fn new(display: &'a Display, width: u32, height: u32) -> Result<Self, TestError> {
+        unsafe {
+            let dpy = display.display;
+            let root = xlib::XDefaultRootWindow(dpy);
+            let window = xlib::XCreateSimpleWindow(dpy, root, 0, 0, width, height, 0, 0, 0);
+            if window == 0 {
+                return Err(TestError::WindowCreationFailed);
+            }
+
+            xlib::XMapWindow(dpy, window);
+            xlib::XSync(dpy, xlib::False);
+
+            Ok(TestWindow { display, window })
+        }
+    }
+}
+
+unsafe impl HasRawWindowHandle for TestWindow<'_> {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        RawWindowHandle::Xlib(XlibHandle {
+            window: self.window,
+            display: self.display.display as *mut _,
+            ..XlibHandle::empty()
+        })
+    }
+}
+
+impl Drop for TestWindow<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            xlib::XDestroyWindow(self.display.display, self.window);
+            xlib::XSync(self.display.display, xlib::False);
+        }
+    }
+}
+
+/// Renders with a fixed configuration on Mesa's software rasterizer and compares the result
+/// with a golden image.
+pub struct GoldenTest {
+    width: u32,
+    height: u32,
+    config: GlConfig,
+    tolerance: u8,
+    output_dir: Option<PathBuf>,
+    bless: bool,
+}
+
+impl GoldenTest {
+    pub fn new(width: u32, height: u32) -> GoldenTest {
+        GoldenTest {
+            width,
+            height,
+            config: GoldenTest::default_config(),
+            tolerance: 0,
+            output_dir: None,
+            bless: std::env::var_os("RAW_GL_BLESS").is_some(),
+        }
+    }
+
+    /// A 3.3 core, RGBA8, non-multisampled, linear configuration, so that images don't depend
+    /// on the defaults of the machine running the test.
+    pub fn default_config() -> GlConfig {
+        GlConfig {
+            version: (3, 3),
+            profile: Profile::Core,
+            red_bits: 8,
+            green_bits: 8,
+            blue_bits: 8,
+            alpha_bits: 8,
+            depth_bits: 24,
+            stencil_bits: 8,
+            color_format: ColorFormat::Fixed,
+            samples: None,
+            srgb: false,
+            double_buffer: true,
+            stereo: false,
+            vsync: false,
+            matching: Matching::AtLeast,
+            rendering: DirectRendering::Prefer,
+            ..GlConfig::default()
+        }
+    }
+
+    pub fn config(mut self, config: GlConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// The largest per-channel difference that still counts as a match.
+    pub fn tolerance(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Where the rendered and difference images are written on a mismatch. Defaults to the
+    /// golden image's directory.
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    /// Whether to write rendered images as the new golden images instead of comparing against
+    /// them. Defaults to whether `RAW_GL_BLESS` is set.
+    pub fn bless(mut self, bless: bool) -> Self {
+        self.bless = bless;
+        self
+    }
+
+    /// Creates a window and context, calls `render` with the context current, and reads back
+    /// the back buffer. See the [module docs](self) for the environment to run under.
+    pub fn render(
+        &self,
+        display: &Display,
+        render: impl FnOnce(&GlContext),
+    ) -> Result<Image, TestError> {
+        let window = TestWindow::new(display, self.width, self.height)?;
+
+        unsafe {
+            let context = GlContext::create(&window, self.config.clone())?;
+            context.make_current();
+            render(&context);
+            let image =
+                context.read_pixels(Region::full(self.width, self.height), PixelFormat::Rgba8);
+            context.make_not_current();
+
+            Ok(image?)
+        }
+    }
+
+    /// Renders and compares against `golden`.
+    pub fn run(
+        &self,
+        display: &Display,
+        golden: impl AsRef<Path>,
+        render: impl FnOnce(&GlContext),
+    ) -> Result<(), TestError> {
+        let image = self.render(display, render)?;
+        self.compare(&image, golden)
+    }
+
+    /// Compares `image` with the PNG at `golden`, writing `<name>.actual.png` and
+    /// `<name>.diff.png` if they differ.
+    pub fn compare(&self, image: &Image, golden: impl AsRef<Path>) -> Result<(), TestError> {
+        let golden = golden.as_ref();
+        let actual = to_rgba(image);
+
+        if self.bless {
+            actual.save_with_format(golden, image::ImageFormat::Png)?;
+            return Ok(());
+        }
+
+        let expected = image::open(golden)?.to_rgba8();
+        if expected.dimensions() != actual.dimensions() {
+            return Err(TestError::SizeMismatch {
+                expected: expected.dimensions(),
+                actual: actual.dimensions(),
+            });
+        }
+
+        let Diff {
+            image: diff,
+            pixels,
+            max_difference,
+        } = diff(&expected, &actual, self.tolerance);
+        if pixels == 0 {
+            return Ok(());
+        }
+
+        let dir = match &self.output_dir {
+            Some(dir) => dir.clone(),
+            None => golden.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        let stem = golden.file_stem().unwrap_or_default().to_string_lossy();
+        let actual_path = dir.join(format!("{}.actual.png", stem));
+        let diff_path = dir.join(format!("{}.diff.png", stem));
+        actual.save_with_format(&actual_path, image::ImageFormat::Png)?;
+        diff.save_with_format(&diff_path, image::ImageFormat::Png)?;
+
+        Err(TestError::Mismatch {
+            pixels,
+            max_difference,
+            actual: actual_path,
+            diff: diff_path,
+        })
+    }
+}
+
+/// The differences between two images of the same size.
+struct Diff {
+    /// Mismatched pixels in red, over the dimmed actual image.
+    image: image::RgbaImage,
+    /// The number of pixels with a channel differing by more than the tolerance.
+    pixels: usize,
+    max_difference: u8,
+}
+
+fn diff(expected: &image::RgbaImage, actual: &image::RgbaImage, tolerance: u8) -> Diff {
+    let mut image = image::RgbaImage::new(actual.width(), actual.height());
+    let mut pixels = 0;
+    let mut max_difference = 0;
+    for ((e, a), d) in expected
+        .pixels()
+        .zip(actual.pixels())
+        .zip(image.pixels_mut())
+    {
+        let difference =
+            e.0.iter()
+                .zip(&a.0)
+                .map(|(e, a)| e.abs_diff(*a))
+                .max()
+                .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+
+        *d = if difference > tolerance {
+            pixels += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            // Matching pixels are shown dimmed, so the mismatches stand out.
+            let luma = (a.0[0] as u32 + a.0[1] as u32 + a.0[2] as u32) / 3 / 4;
+            image::Rgba([luma as u8, luma as u8, luma as u8, 255])
+        };
+    }
+
+    Diff {
+        image,
+        pixels,
+        max_difference,
+    }
+}
+
+fn to_rgba(image: &Image) -> image::RgbaImage {
+    let data = match image.format {
+        PixelFormat::Rgba8 => image.data.clone(),
+        PixelFormat::Rgb8 => image
+            .data
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+    };
+
+    image::RgbaImage::from_raw(image.width, image.height, data).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ColorSpace;
+
+    fn image(pixels: &[[u8; 4]]) -> image::RgbaImage {
+        let data = pixels.iter().flatten().copied().collect();
+        image::RgbaImage::from_raw(pixels.len() as u32, 1, data).unwrap()
+    }
+
+    #[test]
+    fn diff_within_tolerance() {
+        let expected = image(&[[100, 100, 100, 255], [0, 0, 0, 255]]);
+        let actual = image(&[[102, 99, 100, 255], [0, 0, 0, 255]]);
+
+        let result = diff(&expected, &actual, 2);
+        assert_eq!(result.pixels, 0);
+        assert_eq!(result.max_difference, 2);
+    }
+
+    #[test]
+    fn diff_beyond_tolerance() {
+        let expected = image(&[[100, 100, 100, 255], [0, 0, 0, 255], [8, 8, 8, 255]]);
+        let actual = image(&[[103, 100, 100, 255], [0, 0, 0, 0], [8, 8, 8, 255]]);
+
+        let result = diff(&expected, &actual, 2);
+        assert_eq!(result.pixels, 2);
+        assert_eq!(result.max_difference, 255);
+        assert_eq!(result.image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(result.image.get_pixel(1, 0).0, [255, 0, 0, 255]);
+        // Matching pixels are a quarter of their brightness.
+        assert_eq!(result.image.get_pixel(2, 0).0, [2, 2, 2, 255]);
+    }
+
+    #[test]
+    fn compare_writes_actual_and_diff() {
+        let dir = std::env::temp_dir().join(format!("raw-gl-golden-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let golden = dir.join("square.png");
+        image(&[[255, 0, 0, 255], [0, 255, 0, 255]])
+            .save_with_format(&golden, image::ImageFormat::Png)
+            .unwrap();
+
+        let rendered = |data: Vec<u8>| Image {
+            width: 2,
+            height: 1,
+            format: PixelFormat::Rgb8,
+            color_space: ColorSpace::Linear,
+            data,
+        };
+        let test = GoldenTest::new(2, 1).tolerance(1).bless(false);
+        test.compare(&rendered(vec![254, 0, 0, 0, 255, 1]), &golden)
+            .unwrap();
+
+        let result = test.compare(&rendered(vec![255, 0, 0, 0, 0, 255]), &golden);
+        match result {
+            Err(TestError::Mismatch {
+                pixels: 1,
+                max_difference: 255,
+                actual,
+                diff,
+            }) => {
+                assert_eq!(actual, dir.join("square.actual.png"));
+                assert_eq!(
+                    image::open(&diff).unwrap().to_rgba8().get_pixel(1, 0).0,
+                    [255, 0, 0, 255]
+                );
+            }
+            result => panic!("{:?}", result),
+        }
+
+        let narrow = Image {
+            width: 1,
+            ..rendered(vec![255, 0, 0])
+        };
+        assert!(matches!(
+            test.compare(&narrow, &golden),
+            Err(TestError::SizeMismatch {
+                expected: (2, 1),
+                actual: (1, 1),
+            })
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+//! Instrumentation with `tracing`, enabled by the `tracing` feature. The `log` feature also
+//! forwards the events to `log` when no `tracing` subscriber is installed. Without either
+//! feature the macros expand to nothing and [`Step`] is zero-sized.
+
+// Only the GLX backend is instrumented so far.
+#![cfg_attr(not(target_os = "linux"), allow(unused_macros, dead_code))]
+
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::trace!($($arg)*);
+        }
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::debug!($($arg)*);
+        }
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::warn!($($arg)*);
+        }
+    };
+}
+
+/// A span for one step of context creation, which logs how long the step took when dropped.
+pub(crate) struct Step {
+    #[cfg(feature = "tracing")]
+    name: &'static str,
+    #[cfg(feature = "tracing")]
+    start: Instant,
+    #[cfg(feature = "tracing")]
+    _span: tracing::span::EnteredSpan,
+}
+
+#[inline]
+pub(crate) fn step(_name: &'static str) -> Step {
+    Step {
+        #[cfg(feature = "tracing")]
+        name: _name,
+        #[cfg(feature = "tracing")]
+        start: Instant::now(),
+        #[cfg(feature = "tracing")]
+        _span: tracing::debug_span!("step", name = _name).entered(),
+    }
+}
+
+impl Step {
+    /// Ends the step before the end of the scope.
+    #[inline]
+    pub(crate) fn end(self) {}
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Step {
+    fn drop(&mut self) {
+        tracing::debug!(elapsed = ?self.start.elapsed(), "{} done", self.name);
+    }
+}
@@ -1,16 +1,18 @@
-use std::ffi::{c_void, CString, OsStr};
+use std::ffi::{c_void, CStr, CString, OsStr};
+use std::os::raw::c_char;
 use std::os::windows::ffi::OsStrExt;
 
-use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use raw_window_handle::RawWindowHandle;
 
 use winapi::shared::minwindef::{HINSTANCE, HMODULE};
 use winapi::shared::ntdef::WCHAR;
 use winapi::shared::windef::{HDC, HGLRC, HWND};
 use winapi::um::libloaderapi::{FreeLibrary, GetProcAddress, LoadLibraryA};
 use winapi::um::wingdi::{
-    wglCreateContext, wglDeleteContext, wglGetProcAddress, wglMakeCurrent, ChoosePixelFormat,
-    DescribePixelFormat, SetPixelFormat, SwapBuffers, PFD_DOUBLEBUFFER, PFD_DRAW_TO_WINDOW,
-    PFD_MAIN_PLANE, PFD_SUPPORT_OPENGL, PFD_TYPE_RGBA, PIXELFORMATDESCRIPTOR,
+    wglCreateContext, wglDeleteContext, wglGetCurrentContext, wglGetCurrentDC, wglGetProcAddress,
+    wglMakeCurrent, ChoosePixelFormat, DescribePixelFormat, GetPixelFormat, SetPixelFormat,
+    SwapBuffers, PFD_DOUBLEBUFFER, PFD_DRAW_TO_WINDOW, PFD_MAIN_PLANE, PFD_STEREO,
+    PFD_SUPPORT_OPENGL, PFD_TYPE_RGBA, PIXELFORMATDESCRIPTOR,
 };
 use winapi::um::winnt::IMAGE_DOS_HEADER;
 use winapi::um::winuser::{
@@ -18,7 +20,11 @@ use winapi::um::winuser::{
     UnregisterClassW, CS_OWNDC, CW_USEDEFAULT, WNDCLASSW,
 };
 
-use crate::{GlConfig, GlError, Profile};
+use crate::{
+    Backend, ColorFormat, GlConfig, GlError, GlxInfo, Matching, Ownership, Profile, RawGlHandles,
+};
+
+pub const BACKEND: Backend = Backend::Wgl;
 
 // See https://www.khronos.org/registry/OpenGL/extensions/ARB/WGL_ARB_create_context.txt
 
@@ -36,10 +42,14 @@ const WGL_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB: i32 = 0x00000002;
 type WglChoosePixelFormatARB =
     extern "system" fn(HDC, *const i32, *const f32, u32, *mut i32, *mut u32) -> i32;
 
+type WglGetPixelFormatAttribivARB =
+    extern "system" fn(HDC, i32, i32, u32, *const i32, *mut i32) -> i32;
+
 const WGL_DRAW_TO_WINDOW_ARB: i32 = 0x2001;
 const WGL_ACCELERATION_ARB: i32 = 0x2003;
 const WGL_SUPPORT_OPENGL_ARB: i32 = 0x2010;
 const WGL_DOUBLE_BUFFER_ARB: i32 = 0x2011;
+const WGL_STEREO_ARB: i32 = 0x2012;
 const WGL_PIXEL_TYPE_ARB: i32 = 0x2013;
 const WGL_RED_BITS_ARB: i32 = 0x2015;
 const WGL_GREEN_BITS_ARB: i32 = 0x2017;
@@ -51,6 +61,14 @@ const WGL_STENCIL_BITS_ARB: i32 = 0x2023;
 const WGL_FULL_ACCELERATION_ARB: i32 = 0x2027;
 const WGL_TYPE_RGBA_ARB: i32 = 0x202B;
 
+// See https://www.khronos.org/registry/OpenGL/extensions/ARB/ARB_color_buffer_float.txt
+
+const WGL_TYPE_RGBA_FLOAT_ARB: i32 = 0x21A0;
+
+// See https://www.khronos.org/registry/OpenGL/extensions/EXT/EXT_packed_float.txt
+
+const WGL_TYPE_RGBA_UNSIGNED_FLOAT_EXT: i32 = 0x21A8;
+
 // See https://www.khronos.org/registry/OpenGL/extensions/ARB/ARB_multisample.txt
 
 const WGL_SAMPLE_BUFFERS_ARB: i32 = 0x2041;
@@ -60,27 +78,142 @@ const WGL_SAMPLES_ARB: i32 = 0x2042;
 
 const WGL_FRAMEBUFFER_SRGB_CAPABLE_ARB: i32 = 0x20A9;
 
+// See https://www.khronos.org/registry/OpenGL/extensions/ARB/WGL_ARB_extensions_string.txt
+
+type WglGetExtensionsStringARB = extern "system" fn(HDC) -> *const c_char;
+
 // See https://www.khronos.org/registry/OpenGL/extensions/EXT/WGL_EXT_swap_control.txt
 
 type WglSwapIntervalEXT = extern "system" fn(i32) -> i32;
 
+/// The context and device context current on a thread, saved to be restored later.
+pub struct Previous {
+    hdc: HDC,
+    hglrc: HGLRC,
+}
+
 pub struct GlContext {
     hwnd: HWND,
     hdc: HDC,
     hglrc: HGLRC,
     gl_library: HMODULE,
+    config: GlConfig,
+    extensions: Vec<String>,
+    ownership: Ownership,
 }
 
 extern "C" {
     static __ImageBase: IMAGE_DOS_HEADER;
 }
 
+unsafe fn query_extensions(
+    get_extensions_string: Option<WglGetExtensionsStringARB>,
+    hdc: HDC,
+) -> Vec<String> {
+    let extensions = match get_extensions_string {
+        Some(get_extensions_string) => get_extensions_string(hdc),
+        None => return Vec::new(),
+    };
+
+    if extensions.is_null() {
+        return Vec::new();
+    }
+
+    CStr::from_ptr(extensions)
+        .to_string_lossy()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Describes `pixel_format` from its classic descriptor, taking everything the descriptor
+/// doesn't cover from `base`. The descriptor has no multisample or sRGB information.
+unsafe fn describe_pfd(hdc: HDC, pixel_format: i32, base: &GlConfig) -> GlConfig {
+    let mut pfd: PIXELFORMATDESCRIPTOR = std::mem::zeroed();
+    DescribePixelFormat(
+        hdc,
+        pixel_format,
+        std::mem::size_of::<PIXELFORMATDESCRIPTOR>() as u32,
+        &mut pfd,
+    );
+
+    GlConfig {
+        red_bits: pfd.cRedBits,
+        green_bits: pfd.cGreenBits,
+        blue_bits: pfd.cBlueBits,
+        alpha_bits: pfd.cAlphaBits,
+        depth_bits: pfd.cDepthBits,
+        stencil_bits: pfd.cStencilBits,
+        samples: None,
+        srgb: false,
+        double_buffer: pfd.dwFlags & PFD_DOUBLEBUFFER != 0,
+        stereo: pfd.dwFlags & PFD_STEREO != 0,
+        ..base.clone()
+    }
+}
+
+/// The pixel formats matching `config`, best first.
+unsafe fn choose_pixel_formats(
+    choose: WglChoosePixelFormatARB,
+    hdc: HDC,
+    config: &GlConfig,
+) -> Vec<i32> {
+    let pixel_type = match config.color_format {
+        ColorFormat::Fixed => WGL_TYPE_RGBA_ARB,
+        ColorFormat::Float => WGL_TYPE_RGBA_FLOAT_ARB,
+        ColorFormat::PackedFloat => WGL_TYPE_RGBA_UNSIGNED_FLOAT_EXT,
+    };
+
+    #[rustfmt::skip]
+    let pixel_format_attribs = [
+        WGL_DRAW_TO_WINDOW_ARB, 1,
+        WGL_ACCELERATION_ARB, WGL_FULL_ACCELERATION_ARB,
+        WGL_SUPPORT_OPENGL_ARB, 1,
+        WGL_DOUBLE_BUFFER_ARB, config.double_buffer as i32,
+        WGL_STEREO_ARB, config.stereo as i32,
+        WGL_PIXEL_TYPE_ARB, pixel_type,
+        WGL_RED_BITS_ARB, config.red_bits as i32,
+        WGL_GREEN_BITS_ARB, config.green_bits as i32,
+        WGL_BLUE_BITS_ARB, config.blue_bits as i32,
+        WGL_ALPHA_BITS_ARB, config.alpha_bits as i32,
+        WGL_DEPTH_BITS_ARB, config.depth_bits as i32,
+        WGL_STENCIL_BITS_ARB, config.stencil_bits as i32,
+        WGL_SAMPLE_BUFFERS_ARB, config.samples.is_some() as i32,
+        WGL_SAMPLES_ARB, config.samples.unwrap_or(0) as i32,
+        WGL_FRAMEBUFFER_SRGB_CAPABLE_ARB, config.srgb as i32,
+        0,
+    ];
+
+    // Query the number of matching formats first, then fetch them all.
+    let mut num_formats = 0;
+    choose(
+        hdc,
+        pixel_format_attribs.as_ptr(),
+        std::ptr::null(),
+        0,
+        std::ptr::null_mut(),
+        &mut num_formats,
+    );
+
+    let mut pixel_formats = vec![0; num_formats as usize];
+    if num_formats > 0 {
+        choose(
+            hdc,
+            pixel_format_attribs.as_ptr(),
+            std::ptr::null(),
+            pixel_formats.len() as u32,
+            pixel_formats.as_mut_ptr(),
+            &mut num_formats,
+        );
+        pixel_formats.truncate(num_formats as usize);
+    }
+
+    pixel_formats
+}
+
 impl GlContext {
-    pub unsafe fn create(
-        parent: &impl HasRawWindowHandle,
-        config: GlConfig,
-    ) -> Result<GlContext, GlError> {
-        let handle = if let RawWindowHandle::Windows(handle) = parent.raw_window_handle() {
+    pub unsafe fn create(handle: RawWindowHandle, config: GlConfig) -> Result<GlContext, GlError> {
+        let handle = if let RawWindowHandle::Windows(handle) = handle {
             handle
         } else {
             return Err(GlError::InvalidWindowHandle);
@@ -180,6 +313,28 @@ impl GlContext {
             }
         };
 
+        #[allow(non_snake_case)]
+        let wglGetPixelFormatAttribivARB: Option<WglGetPixelFormatAttribivARB> = {
+            let symbol = CString::new("wglGetPixelFormatAttribivARB").unwrap();
+            let addr = wglGetProcAddress(symbol.as_ptr());
+            if !addr.is_null() {
+                Some(std::mem::transmute(addr))
+            } else {
+                None
+            }
+        };
+
+        #[allow(non_snake_case)]
+        let wglGetExtensionsStringARB: Option<WglGetExtensionsStringARB> = {
+            let symbol = CString::new("wglGetExtensionsStringARB").unwrap();
+            let addr = wglGetProcAddress(symbol.as_ptr());
+            if !addr.is_null() {
+                Some(std::mem::transmute(addr))
+            } else {
+                None
+            }
+        };
+
         #[allow(non_snake_case)]
         let wglSwapIntervalEXT: Option<WglSwapIntervalEXT> = {
             let symbol = CString::new("wglSwapIntervalEXT").unwrap();
@@ -202,35 +357,121 @@ impl GlContext {
 
         let hdc = GetDC(hwnd);
 
-        #[rustfmt::skip]
-        let pixel_format_attribs = [
-            WGL_DRAW_TO_WINDOW_ARB, 1,
-            WGL_ACCELERATION_ARB, WGL_FULL_ACCELERATION_ARB,
-            WGL_SUPPORT_OPENGL_ARB, 1,
-            WGL_DOUBLE_BUFFER_ARB, config.double_buffer as i32,
-            WGL_PIXEL_TYPE_ARB, WGL_TYPE_RGBA_ARB,
-            WGL_RED_BITS_ARB, config.red_bits as i32,
-            WGL_GREEN_BITS_ARB, config.green_bits as i32,
-            WGL_BLUE_BITS_ARB, config.blue_bits as i32,
-            WGL_ALPHA_BITS_ARB, config.alpha_bits as i32,
-            WGL_DEPTH_BITS_ARB, config.depth_bits as i32,
-            WGL_STENCIL_BITS_ARB, config.stencil_bits as i32,
-            WGL_SAMPLE_BUFFERS_ARB, config.samples.is_some() as i32,
-            WGL_SAMPLES_ARB, config.samples.unwrap_or(0) as i32,
-            WGL_FRAMEBUFFER_SRGB_CAPABLE_ARB, config.srgb as i32,
-            0,
-        ];
+        let extensions = query_extensions(wglGetExtensionsStringARB, hdc);
+
+        let minimums = config.minimums();
+        let choose = wglChoosePixelFormatARB.unwrap();
+        let pixel_formats = choose_pixel_formats(choose, hdc, &minimums);
+
+        if pixel_formats.is_empty() {
+            if config.color_format != ColorFormat::Fixed {
+                let fixed = GlConfig {
+                    color_format: ColorFormat::Fixed,
+                    ..minimums.clone()
+                };
+                if !choose_pixel_formats(choose, hdc, &fixed).is_empty() {
+                    ReleaseDC(hwnd, hdc);
+                    return Err(GlError::FormatNotSupported);
+                }
+            }
 
-        let mut pixel_format = 0;
-        let mut num_formats = 0;
-        wglChoosePixelFormatARB.unwrap()(
-            hdc,
-            pixel_format_attribs.as_ptr(),
-            std::ptr::null(),
-            1,
-            &mut pixel_format,
-            &mut num_formats,
-        );
+            if config.stereo {
+                let mono = GlConfig {
+                    stereo: false,
+                    ..minimums.clone()
+                };
+                if !choose_pixel_formats(choose, hdc, &mono).is_empty() {
+                    ReleaseDC(hwnd, hdc);
+                    return Err(GlError::StereoNotSupported);
+                }
+            }
+
+            ReleaseDC(hwnd, hdc);
+            return Err(GlError::CreationFailed);
+        }
+
+        let describe_pixel_format = |pixel_format| {
+            // Without WGL_ARB_pixel_format, fall back to the classic descriptor.
+            let get_attribs = match wglGetPixelFormatAttribivARB {
+                Some(get_attribs) => get_attribs,
+                None => return describe_pfd(hdc, pixel_format, &config),
+            };
+
+            #[rustfmt::skip]
+            let query_attribs = [
+                WGL_RED_BITS_ARB,
+                WGL_GREEN_BITS_ARB,
+                WGL_BLUE_BITS_ARB,
+                WGL_ALPHA_BITS_ARB,
+                WGL_DEPTH_BITS_ARB,
+                WGL_STENCIL_BITS_ARB,
+                WGL_PIXEL_TYPE_ARB,
+                WGL_SAMPLE_BUFFERS_ARB,
+                WGL_SAMPLES_ARB,
+                WGL_FRAMEBUFFER_SRGB_CAPABLE_ARB,
+                WGL_DOUBLE_BUFFER_ARB,
+                WGL_STEREO_ARB,
+            ];
+            let mut values = [0; 12];
+            let ok = get_attribs(
+                hdc,
+                pixel_format,
+                0,
+                query_attribs.len() as u32,
+                query_attribs.as_ptr(),
+                values.as_mut_ptr(),
+            );
+            if ok == 0 {
+                return describe_pfd(hdc, pixel_format, &config);
+            }
+
+            GlConfig {
+                red_bits: values[0] as u8,
+                green_bits: values[1] as u8,
+                blue_bits: values[2] as u8,
+                alpha_bits: values[3] as u8,
+                depth_bits: values[4] as u8,
+                stencil_bits: values[5] as u8,
+                color_format: match values[6] {
+                    WGL_TYPE_RGBA_FLOAT_ARB => ColorFormat::Float,
+                    WGL_TYPE_RGBA_UNSIGNED_FLOAT_EXT => ColorFormat::PackedFloat,
+                    _ => ColorFormat::Fixed,
+                },
+                samples: if values[7] != 0 {
+                    Some(values[8] as u8)
+                } else {
+                    None
+                },
+                srgb: values[9] != 0,
+                double_buffer: values[10] != 0,
+                stereo: values[11] != 0,
+                ..config.clone()
+            }
+        };
+
+        let candidates: Vec<GlConfig> = pixel_formats
+            .iter()
+            .map(|&pixel_format| describe_pixel_format(pixel_format))
+            .collect();
+
+        let index = match config.matching {
+            Matching::AtLeast => 0,
+            Matching::Exact => {
+                match candidates
+                    .iter()
+                    .position(|actual| config.matches_exactly(actual))
+                {
+                    Some(index) => index,
+                    None => {
+                        ReleaseDC(hwnd, hdc);
+                        return Err(GlError::NoExactMatch(config.closest(&candidates)));
+                    }
+                }
+            }
+        };
+
+        let pixel_format = pixel_formats[index];
+        let actual_config = candidates[index].clone();
 
         let mut pfd: PIXELFORMATDESCRIPTOR = std::mem::zeroed();
         DescribePixelFormat(
@@ -267,7 +508,12 @@ impl GlContext {
         let gl_library = LoadLibraryA(gl_library_name.as_ptr());
 
         wglMakeCurrent(hdc, hglrc);
-        wglSwapIntervalEXT.unwrap()(config.vsync as i32);
+        match wglSwapIntervalEXT {
+            Some(swap_interval) => {
+                swap_interval(config.vsync as i32);
+            }
+            None => warn!("WGL_EXT_swap_control unavailable, vsync won't be set"),
+        }
         wglMakeCurrent(hdc, std::ptr::null_mut());
 
         Ok(GlContext {
@@ -275,6 +521,57 @@ impl GlContext {
             hdc,
             hglrc,
             gl_library,
+            config: actual_config,
+            extensions,
+            ownership: Ownership::Owned,
+        })
+    }
+
+    pub unsafe fn from_raw(
+        handles: RawGlHandles,
+        ownership: Ownership,
+    ) -> Result<GlContext, GlError> {
+        let (hwnd, hdc, hglrc) = match handles {
+            RawGlHandles::Wgl { hwnd, hdc, hglrc } => (hwnd as HWND, hdc as HDC, hglrc as HGLRC),
+            _ => return Err(GlError::InvalidWindowHandle),
+        };
+
+        if hdc.is_null() || hglrc.is_null() {
+            return Err(GlError::InvalidWindowHandle);
+        }
+
+        let config = describe_pfd(hdc, GetPixelFormat(hdc), &GlConfig::default());
+
+        // wglGetProcAddress needs a current context.
+        let prev_hdc = wglGetCurrentDC();
+        let prev_hglrc = wglGetCurrentContext();
+        wglMakeCurrent(hdc, hglrc);
+
+        #[allow(non_snake_case)]
+        let wglGetExtensionsStringARB: Option<WglGetExtensionsStringARB> = {
+            let symbol = CString::new("wglGetExtensionsStringARB").unwrap();
+            let addr = wglGetProcAddress(symbol.as_ptr());
+            if !addr.is_null() {
+                Some(std::mem::transmute(addr))
+            } else {
+                None
+            }
+        };
+        let extensions = query_extensions(wglGetExtensionsStringARB, hdc);
+
+        wglMakeCurrent(prev_hdc, prev_hglrc);
+
+        let gl_library_name = CString::new("opengl32.dll").unwrap();
+        let gl_library = LoadLibraryA(gl_library_name.as_ptr());
+
+        Ok(GlContext {
+            hwnd,
+            hdc,
+            hglrc,
+            gl_library,
+            config,
+            extensions,
+            ownership,
         })
     }
 
@@ -286,8 +583,37 @@ impl GlContext {
         wglMakeCurrent(self.hdc, std::ptr::null_mut());
     }
 
-    pub fn get_proc_address(&self, symbol: &str) -> *const c_void {
-        let symbol = CString::new(symbol).unwrap();
+    /// Makes the context current, returning what was current before for [`Self::restore`].
+    pub unsafe fn make_current_saving(&self) -> Previous {
+        let previous = Previous {
+            hdc: wglGetCurrentDC(),
+            hglrc: wglGetCurrentContext(),
+        };
+        self.make_current();
+        previous
+    }
+
+    pub unsafe fn restore(&self, previous: Previous) {
+        if previous.hglrc.is_null() {
+            self.make_not_current();
+        } else {
+            wglMakeCurrent(previous.hdc, previous.hglrc);
+        }
+    }
+
+    pub fn raw_handles(&self) -> RawGlHandles {
+        RawGlHandles::Wgl {
+            hwnd: self.hwnd as *mut c_void,
+            hdc: self.hdc as *mut c_void,
+            hglrc: self.hglrc as *mut c_void,
+        }
+    }
+
+    pub fn is_current(&self) -> bool {
+        unsafe { wglGetCurrentContext() == self.hglrc }
+    }
+
+    pub fn get_proc_address_cstr(&self, symbol: &CStr) -> *const c_void {
         let addr = unsafe { wglGetProcAddress(symbol.as_ptr()) as *const c_void };
         if !addr.is_null() {
             addr
@@ -301,14 +627,32 @@ impl GlContext {
             SwapBuffers(self.hdc);
         }
     }
+
+    pub fn actual_config(&self) -> &GlConfig {
+        &self.config
+    }
+
+    pub fn is_direct(&self) -> bool {
+        true
+    }
+
+    pub fn glx_info(&self) -> Option<GlxInfo> {
+        None
+    }
+
+    pub fn platform_extensions(&self) -> &[String] {
+        &self.extensions
+    }
 }
 
 impl Drop for GlContext {
     fn drop(&mut self) {
         unsafe {
-            wglMakeCurrent(std::ptr::null_mut(), std::ptr::null_mut());
-            wglDeleteContext(self.hglrc);
-            ReleaseDC(self.hwnd, self.hdc);
+            if self.ownership == Ownership::Owned {
+                wglMakeCurrent(std::ptr::null_mut(), std::ptr::null_mut());
+                wglDeleteContext(self.hglrc);
+                ReleaseDC(self.hwnd, self.hdc);
+            }
             FreeLibrary(self.gl_library);
         }
     }
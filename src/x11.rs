@@ -1,12 +1,18 @@
-use std::ffi::{c_void, CString};
-use std::os::raw::{c_int, c_ulong};
+use std::ffi::{c_void, CStr};
+use std::os::raw::{c_char, c_int, c_ulong};
 
-use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use raw_window_handle::RawWindowHandle;
 
 use x11::glx;
 use x11::xlib;
 
-use crate::{GlConfig, GlError, Profile};
+use crate::trace;
+use crate::{
+    Backend, ColorFormat, DirectRendering, GlConfig, GlError, GlxInfo, Matching, Ownership,
+    Profile, RawGlHandles,
+};
+
+pub const BACKEND: Backend = Backend::Glx;
 
 // See https://www.khronos.org/registry/OpenGL/extensions/ARB/GLX_ARB_create_context.txt
 
@@ -27,27 +33,326 @@ type GlXSwapIntervalEXT =
 
 const GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB: i32 = 0x20B2;
 
+// See https://www.khronos.org/registry/OpenGL/extensions/ARB/ARB_color_buffer_float.txt
+
+const GLX_RGBA_FLOAT_TYPE_ARB: i32 = 0x20B9;
+const GLX_RGBA_FLOAT_BIT_ARB: i32 = 0x0004;
+
+// See https://www.khronos.org/registry/OpenGL/extensions/EXT/EXT_packed_float.txt
+
+const GLX_RGBA_UNSIGNED_FLOAT_TYPE_EXT: i32 = 0x20B1;
+const GLX_RGBA_UNSIGNED_FLOAT_BIT_EXT: i32 = 0x0008;
+
+/// Whether `$fault` was injected with [`crate::fault::inject`]. Always false in release builds.
+macro_rules! injected {
+    ($fault:ident) => {{
+        #[cfg(debug_assertions)]
+        let injected = crate::fault::is_injected(crate::fault::Fault::$fault);
+        #[cfg(not(debug_assertions))]
+        let injected = false;
+        injected
+    }};
+}
+
+unsafe fn make_current(
+    display: *mut xlib::Display,
+    drawable: glx::GLXDrawable,
+    context: glx::GLXContext,
+) -> bool {
+    // Releasing the current context, as make_not_current and restore do, isn't faulted.
+    if !context.is_null() && injected!(MakeCurrent) {
+        return false;
+    }
+
+    glx::glXMakeCurrent(display, drawable, context) != 0
+}
+
 extern "C" fn err_handler(_dpy: *mut xlib::Display, _err: *mut xlib::XErrorEvent) -> i32 {
     0
 }
 
-fn get_proc_address(symbol: &str) -> *const c_void {
-    let symbol = CString::new(symbol).unwrap();
-    unsafe { glx::glXGetProcAddress(symbol.as_ptr() as *const u8).unwrap() as *const c_void }
+fn get_proc_address(symbol: &CStr) -> *const c_void {
+    match unsafe { glx::glXGetProcAddress(symbol.as_ptr() as *const u8) } {
+        Some(addr) => addr as *const c_void,
+        None => std::ptr::null(),
+    }
+}
+
+unsafe fn query_extensions(display: *mut xlib::Display, screen: c_int) -> Vec<String> {
+    let extensions = glx::glXQueryExtensionsString(display, screen);
+    if extensions.is_null() {
+        return Vec::new();
+    }
+
+    CStr::from_ptr(extensions)
+        .to_string_lossy()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Formats a zero-terminated GLX attribute list as `NAME = value` pairs.
+#[cfg(feature = "tracing")]
+fn attrib_list(attribs: &[c_int]) -> String {
+    let name = |attrib| match attrib {
+        glx::GLX_X_RENDERABLE => "GLX_X_RENDERABLE",
+        glx::GLX_X_VISUAL_TYPE => "GLX_X_VISUAL_TYPE",
+        glx::GLX_DRAWABLE_TYPE => "GLX_DRAWABLE_TYPE",
+        glx::GLX_RENDER_TYPE => "GLX_RENDER_TYPE",
+        glx::GLX_RED_SIZE => "GLX_RED_SIZE",
+        glx::GLX_GREEN_SIZE => "GLX_GREEN_SIZE",
+        glx::GLX_BLUE_SIZE => "GLX_BLUE_SIZE",
+        glx::GLX_ALPHA_SIZE => "GLX_ALPHA_SIZE",
+        glx::GLX_DEPTH_SIZE => "GLX_DEPTH_SIZE",
+        glx::GLX_STENCIL_SIZE => "GLX_STENCIL_SIZE",
+        glx::GLX_DOUBLEBUFFER => "GLX_DOUBLEBUFFER",
+        glx::GLX_STEREO => "GLX_STEREO",
+        glx::GLX_SAMPLE_BUFFERS => "GLX_SAMPLE_BUFFERS",
+        glx::GLX_SAMPLES => "GLX_SAMPLES",
+        GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB => "GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB",
+        glx::arb::GLX_CONTEXT_MAJOR_VERSION_ARB => "GLX_CONTEXT_MAJOR_VERSION_ARB",
+        glx::arb::GLX_CONTEXT_MINOR_VERSION_ARB => "GLX_CONTEXT_MINOR_VERSION_ARB",
+        glx::arb::GLX_CONTEXT_PROFILE_MASK_ARB => "GLX_CONTEXT_PROFILE_MASK_ARB",
+        _ => "",
+    };
+
+    attribs
+        .chunks_exact(2)
+        .map(|pair| match name(pair[0]) {
+            "" => format!("{:#x} = {:#x}", pair[0], pair[1]),
+            name => format!("{} = {:#x}", name, pair[1]),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+unsafe fn choose_fb_configs(
+    display: *mut xlib::Display,
+    screen: c_int,
+    config: &GlConfig,
+) -> Vec<glx::GLXFBConfig> {
+    let render_type = match config.color_format {
+        ColorFormat::Fixed => glx::GLX_RGBA_BIT,
+        ColorFormat::Float => GLX_RGBA_FLOAT_BIT_ARB,
+        ColorFormat::PackedFloat => GLX_RGBA_UNSIGNED_FLOAT_BIT_EXT,
+    };
+
+    #[rustfmt::skip]
+    let fb_attribs = [
+        glx::GLX_X_RENDERABLE, 1,
+        glx::GLX_X_VISUAL_TYPE, glx::GLX_TRUE_COLOR,
+        glx::GLX_DRAWABLE_TYPE, glx::GLX_WINDOW_BIT,
+        glx::GLX_RENDER_TYPE, render_type,
+        glx::GLX_RED_SIZE, config.red_bits as i32,
+        glx::GLX_GREEN_SIZE, config.green_bits as i32,
+        glx::GLX_BLUE_SIZE, config.blue_bits as i32,
+        glx::GLX_ALPHA_SIZE, config.alpha_bits as i32,
+        glx::GLX_DEPTH_SIZE, config.depth_bits as i32,
+        glx::GLX_STENCIL_SIZE, config.stencil_bits as i32,
+        glx::GLX_DOUBLEBUFFER, config.double_buffer as i32,
+        glx::GLX_STEREO, config.stereo as i32,
+        glx::GLX_SAMPLE_BUFFERS, config.samples.is_some() as i32,
+        glx::GLX_SAMPLES, config.samples.unwrap_or(0) as i32,
+        GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB, config.srgb as i32,
+        0,
+    ];
+
+    debug!(screen, attribs = %attrib_list(&fb_attribs), "glXChooseFBConfig");
+
+    if injected!(ChooseFbConfig) {
+        warn!("glXChooseFBConfig failure injected");
+        return Vec::new();
+    }
+
+    let mut n_configs = 0;
+    let fb_configs = glx::glXChooseFBConfig(display, screen, fb_attribs.as_ptr(), &mut n_configs);
+
+    if fb_configs.is_null() {
+        debug!("glXChooseFBConfig returned no configs");
+        return Vec::new();
+    }
+
+    let result = std::slice::from_raw_parts(fb_configs, n_configs.max(0) as usize).to_vec();
+    xlib::XFree(fb_configs as *mut c_void);
+
+    debug!(count = result.len(), "glXChooseFBConfig returned configs");
+
+    result
+}
+
+/// Finds the fbconfig a context was created with.
+unsafe fn find_context_fb_config(
+    display: *mut xlib::Display,
+    screen: c_int,
+    context: glx::GLXContext,
+) -> Option<glx::GLXFBConfig> {
+    let mut fb_config_id = 0;
+    glx::glXQueryContext(display, context, glx::GLX_FBCONFIG_ID, &mut fb_config_id);
+
+    let fb_attribs = [glx::GLX_FBCONFIG_ID, fb_config_id, 0];
+    let mut n_configs = 0;
+    let fb_configs = glx::glXChooseFBConfig(display, screen, fb_attribs.as_ptr(), &mut n_configs);
+
+    if fb_configs.is_null() {
+        return None;
+    }
+
+    let fb_config = if n_configs > 0 {
+        Some(*fb_configs)
+    } else {
+        None
+    };
+    xlib::XFree(fb_configs as *mut c_void);
+
+    fb_config
+}
+
+unsafe fn get_fb_config_attrib(
+    display: *mut xlib::Display,
+    fb_config: glx::GLXFBConfig,
+    attrib: c_int,
+) -> c_int {
+    let mut value = 0;
+    glx::glXGetFBConfigAttrib(display, fb_config, attrib, &mut value);
+    value
+}
+
+unsafe fn get_fb_config_depth(display: *mut xlib::Display, fb_config: glx::GLXFBConfig) -> c_int {
+    let visual_info = glx::glXGetVisualFromFBConfig(display, fb_config);
+    if visual_info.is_null() {
+        return 0;
+    }
+
+    let depth = (*visual_info).depth;
+    xlib::XFree(visual_info as *mut c_void);
+
+    depth
+}
+
+/// Every window-renderable framebuffer configuration of `screen`, described like the config
+/// of a context created with it.
+pub unsafe fn enumerate_configs(display: *mut xlib::Display, screen: c_int) -> Vec<GlConfig> {
+    let mut n_configs = 0;
+    let fb_configs = glx::glXGetFBConfigs(display, screen, &mut n_configs);
+    if fb_configs.is_null() {
+        return Vec::new();
+    }
+
+    let render_types = glx::GLX_RGBA_BIT | GLX_RGBA_FLOAT_BIT_ARB | GLX_RGBA_UNSIGNED_FLOAT_BIT_EXT;
+    let configs = std::slice::from_raw_parts(fb_configs, n_configs.max(0) as usize)
+        .iter()
+        .filter(|&&fb_config| {
+            let attrib = |attrib| get_fb_config_attrib(display, fb_config, attrib);
+            attrib(glx::GLX_X_RENDERABLE) != 0
+                && attrib(glx::GLX_DRAWABLE_TYPE) & glx::GLX_WINDOW_BIT != 0
+                && attrib(glx::GLX_RENDER_TYPE) & render_types != 0
+        })
+        .map(|&fb_config| describe_fb_config(display, fb_config, &GlConfig::default()))
+        .collect();
+    xlib::XFree(fb_configs as *mut c_void);
+
+    configs
+}
+
+unsafe fn query_glx_info(display: *mut xlib::Display, screen: c_int) -> GlxInfo {
+    let string = |string: *const c_char| {
+        if string.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(string).to_string_lossy().into_owned()
+        }
+    };
+
+    GlxInfo {
+        client_vendor: string(glx::glXGetClientString(display, glx::GLX_VENDOR)),
+        client_version: string(glx::glXGetClientString(display, glx::GLX_VERSION)),
+        server_vendor: string(glx::glXQueryServerString(display, screen, glx::GLX_VENDOR)),
+        server_version: string(glx::glXQueryServerString(display, screen, glx::GLX_VERSION)),
+    }
+}
+
+/// Reads back the framebuffer attributes of `fb_config`, taking the context attributes
+/// (version, profile, etc.) from `requested`.
+unsafe fn describe_fb_config(
+    display: *mut xlib::Display,
+    fb_config: glx::GLXFBConfig,
+    requested: &GlConfig,
+) -> GlConfig {
+    let attrib = |attrib| get_fb_config_attrib(display, fb_config, attrib);
+
+    let render_type = attrib(glx::GLX_RENDER_TYPE);
+    let color_format = if render_type & GLX_RGBA_FLOAT_BIT_ARB != 0 {
+        ColorFormat::Float
+    } else if render_type & GLX_RGBA_UNSIGNED_FLOAT_BIT_EXT != 0 {
+        ColorFormat::PackedFloat
+    } else {
+        ColorFormat::Fixed
+    };
+
+    GlConfig {
+        red_bits: attrib(glx::GLX_RED_SIZE) as u8,
+        green_bits: attrib(glx::GLX_GREEN_SIZE) as u8,
+        blue_bits: attrib(glx::GLX_BLUE_SIZE) as u8,
+        alpha_bits: attrib(glx::GLX_ALPHA_SIZE) as u8,
+        depth_bits: attrib(glx::GLX_DEPTH_SIZE) as u8,
+        stencil_bits: attrib(glx::GLX_STENCIL_SIZE) as u8,
+        color_format,
+        samples: if attrib(glx::GLX_SAMPLE_BUFFERS) > 0 {
+            Some(attrib(glx::GLX_SAMPLES) as u8)
+        } else {
+            None
+        },
+        srgb: attrib(GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB) != 0,
+        double_buffer: attrib(glx::GLX_DOUBLEBUFFER) != 0,
+        stereo: attrib(glx::GLX_STEREO) != 0,
+        ..requested.clone()
+    }
+}
+
+/// The context and drawables current on a thread, saved to be restored later.
+pub struct Previous {
+    display: *mut xlib::Display,
+    draw: glx::GLXDrawable,
+    read: glx::GLXDrawable,
+    context: glx::GLXContext,
+}
+
+impl Previous {
+    unsafe fn current() -> Previous {
+        Previous {
+            display: glx::glXGetCurrentDisplay(),
+            draw: glx::glXGetCurrentDrawable(),
+            read: glx::glXGetCurrentReadDrawable(),
+            context: glx::glXGetCurrentContext(),
+        }
+    }
+
+    /// Makes the saved context current again, or releases `display`'s if there was none.
+    unsafe fn restore(self, display: *mut xlib::Display) {
+        if self.context.is_null() {
+            make_current(display, 0, std::ptr::null_mut());
+        } else {
+            glx::glXMakeContextCurrent(self.display, self.draw, self.read, self.context);
+        }
+    }
 }
 
 pub struct GlContext {
     window: c_ulong,
     display: *mut xlib::_XDisplay,
     context: glx::GLXContext,
+    fb_config: glx::GLXFBConfig,
+    visual_id: xlib::VisualID,
+    config: GlConfig,
+    direct: bool,
+    extensions: Vec<String>,
+    glx_info: GlxInfo,
+    ownership: Ownership,
 }
 
 impl GlContext {
-    pub unsafe fn create(
-        parent: &impl HasRawWindowHandle,
-        config: GlConfig,
-    ) -> Result<GlContext, GlError> {
-        let handle = if let RawWindowHandle::Xlib(handle) = parent.raw_window_handle() {
+    pub unsafe fn create(handle: RawWindowHandle, config: GlConfig) -> Result<GlContext, GlError> {
+        let handle = if let RawWindowHandle::Xlib(handle) = handle {
             handle
         } else {
             return Err(GlError::InvalidWindowHandle);
@@ -57,105 +362,331 @@ impl GlContext {
             return Err(GlError::InvalidWindowHandle);
         }
 
+        let display = handle.display as *mut xlib::_XDisplay;
+
         let prev_callback = xlib::XSetErrorHandler(Some(err_handler));
+        let result = Self::create_inner(display, handle.window, config);
+        xlib::XSetErrorHandler(prev_callback);
 
-        let display = handle.display as *mut xlib::_XDisplay;
+        result
+    }
+
+    unsafe fn create_inner(
+        display: *mut xlib::Display,
+        window: c_ulong,
+        config: GlConfig,
+    ) -> Result<GlContext, GlError> {
+        let mut window_attribs: xlib::XWindowAttributes = std::mem::zeroed();
+        if xlib::XGetWindowAttributes(display, window, &mut window_attribs) == 0 {
+            return Err(GlError::InvalidWindowHandle);
+        }
 
-        let screen = xlib::XDefaultScreen(display);
+        // Choose configs for the screen the window is actually on rather than the default one,
+        // which differs on multi-screen (Zaphod) setups.
+        let screen = config
+            .screen
+            .unwrap_or_else(|| xlib::XScreenNumberOfScreen(window_attribs.screen));
 
-        #[rustfmt::skip]
-        let fb_attribs = [
-            glx::GLX_X_RENDERABLE, 1,
-            glx::GLX_X_VISUAL_TYPE, glx::GLX_TRUE_COLOR,
-            glx::GLX_DRAWABLE_TYPE, glx::GLX_WINDOW_BIT,
-            glx::GLX_RENDER_TYPE, glx::GLX_RGBA_BIT,
-            glx::GLX_RED_SIZE, config.red_bits as i32,
-            glx::GLX_GREEN_SIZE, config.green_bits as i32,
-            glx::GLX_BLUE_SIZE, config.blue_bits as i32,
-            glx::GLX_ALPHA_SIZE, config.alpha_bits as i32,
-            glx::GLX_DEPTH_SIZE, config.depth_bits as i32,
-            glx::GLX_STENCIL_SIZE, config.stencil_bits as i32,
-            glx::GLX_DOUBLEBUFFER, config.double_buffer as i32,
-            glx::GLX_SAMPLE_BUFFERS, config.samples.is_some() as i32,
-            glx::GLX_SAMPLES, config.samples.unwrap_or(0) as i32,
-            GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB, config.srgb as i32,
-            0,
-        ];
+        debug!(window, screen, ?config, "creating GLX context");
 
-        let mut n_configs = 0;
-        let fb_config = glx::glXChooseFBConfig(display, screen, fb_attribs.as_ptr(), &mut n_configs);
+        // glXGetProcAddress returns a non-null pointer even for functions the driver doesn't
+        // implement, so the extension string decides which functions can be used.
+        let step = trace::step("query_extensions");
+        let extensions = query_extensions(display, screen);
+        let has_extension = |name: &str| extensions.iter().any(|e| e == name);
+        trace!(?extensions, "GLX extensions");
 
-        if n_configs <= 0 {
+        if !has_extension("GLX_ARB_create_context") {
+            warn!("GLX_ARB_create_context isn't supported");
             return Err(GlError::CreationFailed);
         }
+        step.end();
+
+        let step = trace::step("choose_fb_config");
+        let minimums = config.minimums();
+        let mut fb_configs = choose_fb_configs(display, screen, &minimums);
+
+        if fb_configs.is_empty() {
+            if config.color_format != ColorFormat::Fixed {
+                let fixed = GlConfig {
+                    color_format: ColorFormat::Fixed,
+                    ..minimums.clone()
+                };
+                if !choose_fb_configs(display, screen, &fixed).is_empty() {
+                    warn!(color_format = ?config.color_format, "color format not supported");
+                    return Err(GlError::FormatNotSupported);
+                }
+            }
+
+            if config.stereo {
+                let mono = GlConfig {
+                    stereo: false,
+                    ..minimums.clone()
+                };
+                if !choose_fb_configs(display, screen, &mono).is_empty() {
+                    warn!("stereo not supported");
+                    return Err(GlError::StereoNotSupported);
+                }
+            }
+
+            warn!("no framebuffer config matches");
+            return Err(GlError::CreationFailed);
+        }
+
+        if config.matching == Matching::Exact {
+            let described: Vec<GlConfig> = fb_configs
+                .iter()
+                .map(|&fb_config| describe_fb_config(display, fb_config, &config))
+                .collect();
+
+            fb_configs = fb_configs
+                .iter()
+                .zip(&described)
+                .filter(|(_, actual)| {
+                    let matches = config.matches_exactly(actual);
+                    if !matches {
+                        trace!(?actual, "rejected config, not an exact match");
+                    }
+                    matches
+                })
+                .map(|(&fb_config, _)| fb_config)
+                .collect();
+
+            if fb_configs.is_empty() {
+                warn!("no framebuffer config matches exactly");
+                return Err(GlError::NoExactMatch(config.closest(&described)));
+            }
+        }
 
+        // glXChooseFBConfig sorts configs with more color bits first, so on a deep color
+        // server a 10-bit config may come before the one matching the window's visual (and
+        // vice versa). Prefer the window's own visual, then one of the same depth, so that
+        // glXMakeCurrent doesn't fail with BadMatch.
+        let window_visual_id = xlib::XVisualIDFromVisual(window_attribs.visual);
+        let fb_config = fb_configs
+            .iter()
+            .find(|&&fb_config| {
+                get_fb_config_attrib(display, fb_config, glx::GLX_VISUAL_ID) as xlib::VisualID
+                    == window_visual_id
+            })
+            .or_else(|| {
+                fb_configs.iter().find(|&&fb_config| {
+                    get_fb_config_depth(display, fb_config) == window_attribs.depth
+                })
+            })
+            .copied()
+            .unwrap_or(fb_configs[0]);
+        #[cfg(feature = "tracing")]
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            let visual_id = get_fb_config_attrib(display, fb_config, glx::GLX_VISUAL_ID);
+            let chosen = describe_fb_config(display, fb_config, &config);
+            tracing::debug!(
+                window_visual_id,
+                visual_id,
+                candidates = fb_configs.len(),
+                ?chosen,
+                "chose framebuffer config"
+            );
+        }
+        step.end();
+
+        let step = trace::step("lookup_functions");
         #[allow(non_snake_case)]
         let glXCreateContextAttribsARB: GlXCreateContextAttribsARB = {
-            let addr = get_proc_address("glXCreateContextAttribsARB");
-            if addr.is_null() {
+            let addr = crate::with_cstr("glXCreateContextAttribsARB", get_proc_address);
+            trace!(?addr, "glXGetProcAddress(glXCreateContextAttribsARB)");
+            if addr.is_null() || injected!(CreateContextAttribsLookup) {
+                warn!("glXCreateContextAttribsARB not found");
                 return Err(GlError::CreationFailed);
             } else {
-                std::mem::transmute(addr)
+                std::mem::transmute::<*const c_void, GlXCreateContextAttribsARB>(addr)
             }
         };
 
         #[allow(non_snake_case)]
-        let glXSwapIntervalEXT: GlXSwapIntervalEXT = {
-            let addr = get_proc_address("glXSwapIntervalEXT");
-            if addr.is_null() {
-                return Err(GlError::CreationFailed);
+        let glXSwapIntervalEXT: Option<GlXSwapIntervalEXT> = {
+            let addr = crate::with_cstr("glXSwapIntervalEXT", get_proc_address);
+            trace!(?addr, "glXGetProcAddress(glXSwapIntervalEXT)");
+            if addr.is_null()
+                || !has_extension("GLX_EXT_swap_control")
+                || injected!(SwapIntervalLookup)
+            {
+                warn!("GLX_EXT_swap_control unavailable, vsync won't be set");
+                None
             } else {
-                std::mem::transmute(addr)
+                Some(std::mem::transmute::<*const c_void, GlXSwapIntervalEXT>(
+                    addr,
+                ))
             }
         };
 
+        step.end();
+
         let profile_mask = match config.profile {
             Profile::Core => glx::arb::GLX_CONTEXT_CORE_PROFILE_BIT_ARB,
             Profile::Compatibility => glx::arb::GLX_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB,
         };
 
+        let render_type = match config.color_format {
+            ColorFormat::Fixed => glx::GLX_RGBA_TYPE,
+            ColorFormat::Float => GLX_RGBA_FLOAT_TYPE_ARB,
+            ColorFormat::PackedFloat => GLX_RGBA_UNSIGNED_FLOAT_TYPE_EXT,
+        };
+
         #[rustfmt::skip]
         let ctx_attribs = [
             glx::arb::GLX_CONTEXT_MAJOR_VERSION_ARB, config.version.0 as i32,
             glx::arb::GLX_CONTEXT_MINOR_VERSION_ARB, config.version.1 as i32,
             glx::arb::GLX_CONTEXT_PROFILE_MASK_ARB, profile_mask,
+            glx::GLX_RENDER_TYPE, render_type,
             0,
         ];
 
-        let context = glXCreateContextAttribsARB(
-            display,
-            *fb_config,
-            std::ptr::null_mut(),
-            1,
-            ctx_attribs.as_ptr(),
-        );
+        let step = trace::step("create_context");
+        debug!(attribs = %attrib_list(&ctx_attribs), rendering = ?config.rendering, "glXCreateContextAttribsARB");
+
+        let context = if injected!(CreateContext) {
+            warn!("glXCreateContextAttribsARB failure injected");
+            std::ptr::null_mut()
+        } else {
+            glXCreateContextAttribsARB(
+                display,
+                fb_config,
+                std::ptr::null_mut(),
+                (config.rendering != DirectRendering::Indirect) as xlib::Bool,
+                ctx_attribs.as_ptr(),
+            )
+        };
 
         if context.is_null() {
+            warn!("glXCreateContextAttribsARB failed");
             return Err(GlError::CreationFailed);
         }
 
-        glx::glXMakeCurrent(display, handle.window, context);
-        glXSwapIntervalEXT(display, handle.window, config.vsync as i32);
-        glx::glXMakeCurrent(display, 0, std::ptr::null_mut());
+        let direct = glx::glXIsDirect(display, context) != 0;
+        debug!(direct, "created context");
+        if config.rendering == DirectRendering::Require && !direct {
+            warn!("direct rendering required but unavailable");
+            glx::glXDestroyContext(display, context);
+            return Err(GlError::DirectRenderingUnavailable);
+        }
 
-        xlib::XSetErrorHandler(prev_callback);
+        step.end();
+
+        // A context that can't be made current on the window (e.g. BadMatch) is unusable.
+        let previous = Previous::current();
+        if !make_current(display, window, context) {
+            warn!("glXMakeCurrent failed");
+            glx::glXDestroyContext(display, context);
+            return Err(GlError::CreationFailed);
+        }
+        if let Some(swap_interval) = glXSwapIntervalEXT {
+            let _step = trace::step("swap_interval");
+            swap_interval(display, window, config.vsync as i32);
+        }
+        previous.restore(display);
+
+        let config = describe_fb_config(display, fb_config, &config);
+        let visual_id = get_fb_config_attrib(display, fb_config, glx::GLX_VISUAL_ID);
+
+        Ok(GlContext {
+            window,
+            display,
+            context,
+            fb_config,
+            visual_id: visual_id as xlib::VisualID,
+            config,
+            direct,
+            extensions,
+            glx_info: query_glx_info(display, screen),
+            ownership: Ownership::Owned,
+        })
+    }
+
+    pub unsafe fn from_raw(
+        handles: RawGlHandles,
+        ownership: Ownership,
+    ) -> Result<GlContext, GlError> {
+        let (display, context, window, fb_config) = match handles {
+            RawGlHandles::Glx {
+                display,
+                context,
+                drawable,
+                fb_config,
+                ..
+            } => (
+                display as *mut xlib::Display,
+                context as glx::GLXContext,
+                drawable,
+                fb_config as glx::GLXFBConfig,
+            ),
+            _ => return Err(GlError::InvalidWindowHandle),
+        };
+
+        if display.is_null() || context.is_null() {
+            return Err(GlError::InvalidWindowHandle);
+        }
+
+        let mut screen = 0;
+        glx::glXQueryContext(display, context, glx::GLX_SCREEN, &mut screen);
+
+        let fb_config = if fb_config.is_null() {
+            find_context_fb_config(display, screen, context).ok_or(GlError::InvalidWindowHandle)?
+        } else {
+            fb_config
+        };
+
+        let config = describe_fb_config(display, fb_config, &GlConfig::default());
+        let visual_id = get_fb_config_attrib(display, fb_config, glx::GLX_VISUAL_ID);
 
         Ok(GlContext {
-            window: handle.window,
+            window,
             display,
             context,
+            fb_config,
+            visual_id: visual_id as xlib::VisualID,
+            config,
+            direct: glx::glXIsDirect(display, context) != 0,
+            extensions: query_extensions(display, screen),
+            glx_info: query_glx_info(display, screen),
+            ownership,
         })
     }
 
     pub unsafe fn make_current(&self) {
-        glx::glXMakeCurrent(self.display, self.window, self.context);
+        make_current(self.display, self.window, self.context);
     }
 
     pub unsafe fn make_not_current(&self) {
-        glx::glXMakeCurrent(self.display, 0, std::ptr::null_mut());
+        make_current(self.display, 0, std::ptr::null_mut());
+    }
+
+    /// Makes the context current, returning what was current before for [`Self::restore`].
+    pub unsafe fn make_current_saving(&self) -> Previous {
+        let previous = Previous::current();
+        self.make_current();
+        previous
+    }
+
+    pub unsafe fn restore(&self, previous: Previous) {
+        previous.restore(self.display);
     }
 
-    pub fn get_proc_address(&self, symbol: &str) -> *const c_void {
+    pub fn raw_handles(&self) -> RawGlHandles {
+        RawGlHandles::Glx {
+            display: self.display as *mut c_void,
+            context: self.context as *mut c_void,
+            drawable: self.window,
+            fb_config: self.fb_config as *mut c_void,
+            visual_id: self.visual_id,
+        }
+    }
+
+    pub fn is_current(&self) -> bool {
+        unsafe { glx::glXGetCurrentContext() == self.context }
+    }
+
+    pub fn get_proc_address_cstr(&self, symbol: &CStr) -> *const c_void {
         get_proc_address(symbol)
     }
 
@@ -164,8 +695,35 @@ impl GlContext {
             glx::glXSwapBuffers(self.display, self.window);
         }
     }
+
+    pub fn actual_config(&self) -> &GlConfig {
+        &self.config
+    }
+
+    pub fn is_direct(&self) -> bool {
+        self.direct
+    }
+
+    pub fn platform_extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    pub fn glx_info(&self) -> Option<GlxInfo> {
+        Some(self.glx_info.clone())
+    }
 }
 
 impl Drop for GlContext {
-    fn drop(&mut self) {}
+    fn drop(&mut self) {
+        if self.ownership == Ownership::Borrowed {
+            return;
+        }
+
+        unsafe {
+            if glx::glXGetCurrentContext() == self.context {
+                glx::glXMakeCurrent(self.display, 0, std::ptr::null_mut());
+            }
+            glx::glXDestroyContext(self.display, self.context);
+        }
+    }
 }
@@ -0,0 +1,355 @@
+//! Lifecycle tests against the mock backend. Run with `cargo test --features mock`.
+
+#![cfg(all(feature = "mock", target_os = "linux"))]
+
+use raw_gl_context::mock::{self, Call};
+use raw_gl_context::{Backend, GlConfig, GlContext, GlError, Ownership, RawGlHandles};
+use raw_window_handle::unix::XlibHandle;
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+
+struct Window;
+
+unsafe impl HasRawWindowHandle for Window {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        RawWindowHandle::Xlib(XlibHandle::empty())
+    }
+}
+
+fn config() -> GlConfig {
+    GlConfig::builder().backend(Backend::Mock).build().unwrap()
+}
+
+fn create() -> GlContext {
+    unsafe { GlContext::create(&Window, config()).unwrap() }
+}
+
+#[test]
+fn records_calls() {
+    mock::reset();
+
+    let context = create();
+    assert_eq!(context.backend(), Backend::Mock);
+    assert_eq!(context.actual_config().backend, Some(Backend::Mock));
+    unsafe {
+        context.make_current();
+        context.swap_buffers();
+        context.make_not_current();
+    }
+
+    let calls: Vec<Call> = mock::take_calls()
+        .into_iter()
+        .filter(|call| !matches!(call, Call::GetProcAddress { .. }))
+        .collect();
+    let id = match calls[0] {
+        Call::Create { id: Some(id) } => id,
+        ref call => panic!("{:?}", call),
+    };
+    assert_eq!(
+        &calls[calls.len() - 3..],
+        &[
+            Call::MakeCurrent { id },
+            Call::SwapBuffers { id },
+            Call::MakeNotCurrent { id },
+        ]
+    );
+}
+
+#[test]
+fn scripted_failure() {
+    mock::reset();
+    mock::fail_next_create(|| GlError::VersionNotSupported);
+
+    let result = unsafe { GlContext::create(&Window, config()) };
+    assert!(matches!(result, Err(GlError::VersionNotSupported)));
+
+    create();
+}
+
+#[test]
+#[should_panic(expected = "isn't current")]
+fn swap_without_current_context() {
+    create().swap_buffers();
+}
+
+#[test]
+#[should_panic(expected = "made current while current on another thread")]
+fn current_on_two_threads() {
+    let context = create();
+    unsafe {
+        context.make_current();
+    }
+
+    let id = match context.raw_handles() {
+        RawGlHandles::Mock { id } => id,
+        _ => unreachable!(),
+    };
+    let result = std::thread::spawn(move || unsafe {
+        let other = GlContext::from_raw(RawGlHandles::Mock { id }, Ownership::Borrowed).unwrap();
+        other.make_current();
+    })
+    .join();
+
+    if let Err(panic) = result {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+#[cfg(feature = "gl-functions")]
+#[test]
+fn error_checking() {
+    use std::cell::{Cell, RefCell};
+    use std::ffi::c_void;
+    use std::rc::Rc;
+
+    use raw_gl_context::GlCallError;
+
+    thread_local! {
+        static ERROR: Cell<u32> = const { Cell::new(0) };
+    }
+
+    extern "system" fn get_error() -> u32 {
+        ERROR.with(|error| error.replace(0))
+    }
+
+    extern "system" fn clear(_mask: u32) {
+        ERROR.with(|error| error.set(0x0501));
+    }
+
+    mock::reset();
+    mock::set_proc_address("glGetError", get_error as *const c_void);
+    mock::set_proc_address("glClear", clear as *const c_void);
+
+    let mut config = config();
+    config.check_errors = true;
+    let context = unsafe { GlContext::create(&Window, config).unwrap() };
+    let errors = Rc::new(RefCell::new(Vec::new()));
+    let recorded = errors.clone();
+    context
+        .set_error_callback(move |error: &GlCallError| recorded.borrow_mut().push(error.clone()));
+
+    let addr = context.get_proc_address("glClear");
+    assert_ne!(addr, clear as *const c_void);
+    assert_eq!(
+        context.get_proc_address("glGetError"),
+        get_error as *const c_void
+    );
+
+    unsafe {
+        context.make_current();
+        let checked_clear = std::mem::transmute::<*const c_void, extern "system" fn(u32)>(addr);
+        checked_clear(0x4000);
+        context.make_not_current();
+    }
+
+    let errors = errors.borrow();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].function, "glClear");
+    assert_eq!(errors[0].arguments, "16384");
+    assert_eq!(errors[0].error_name(), "GL_INVALID_VALUE");
+}
+
+#[cfg(feature = "gl-functions")]
+#[test]
+fn error_checking_without_callback() {
+    use std::ffi::c_void;
+    use std::process::Command;
+
+    // Errors without a callback abort the process, so the check runs in a child process.
+    const CHILD: &str = "RAW_GL_CONTEXT_ABORT_CHILD";
+
+    extern "system" fn get_error() -> u32 {
+        0x0500
+    }
+
+    extern "system" fn enable(_cap: u32) {}
+
+    if std::env::var_os(CHILD).is_none() {
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args(["error_checking_without_callback", "--exact", "--nocapture"])
+            .env(CHILD, "1")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("glEnable(3042) raised GL_INVALID_ENUM"),
+            "{}",
+            stderr
+        );
+        return;
+    }
+
+    mock::reset();
+    mock::set_proc_address("glGetError", get_error as *const c_void);
+    mock::set_proc_address("glEnable", enable as *const c_void);
+
+    let mut config = config();
+    config.check_errors = true;
+    let context = unsafe { GlContext::create(&Window, config).unwrap() };
+    let addr = context.get_proc_address("glEnable");
+    unsafe {
+        context.make_current();
+        std::mem::transmute::<*const c_void, extern "system" fn(u32)>(addr)(0x0BE2);
+    }
+    unreachable!("the error didn't abort");
+}
+
+#[cfg(feature = "gl-functions")]
+#[test]
+fn error_checking_with_other_context_current() {
+    use std::cell::Cell;
+    use std::ffi::c_void;
+
+    thread_local! {
+        static CALLS: Cell<u32> = const { Cell::new(0) };
+    }
+
+    extern "system" fn get_error() -> u32 {
+        0x0500
+    }
+
+    extern "system" fn disable(_cap: u32) {
+        CALLS.with(|calls| calls.set(calls.get() + 1));
+    }
+
+    mock::reset();
+    mock::set_proc_address("glGetError", get_error as *const c_void);
+    mock::set_proc_address("glDisable", disable as *const c_void);
+
+    let mut config = config();
+    config.check_errors = true;
+    let checked = unsafe { GlContext::create(&Window, config).unwrap() };
+    let addr = checked.get_proc_address("glDisable");
+    let unchecked = create();
+
+    // The call goes through to the function unchecked instead of aborting.
+    unsafe {
+        unchecked.make_current();
+        std::mem::transmute::<*const c_void, extern "system" fn(u32)>(addr)(0x0BE2);
+        unchecked.make_not_current();
+    }
+    assert_eq!(CALLS.with(Cell::get), 1);
+}
+
+#[cfg(feature = "gl-trace")]
+#[test]
+fn trace_recording_and_replay() {
+    use std::cell::RefCell;
+    use std::ffi::c_void;
+
+    use raw_gl_context::replay::Trace;
+
+    thread_local! {
+        static CALLS: RefCell<Vec<(u32, Vec<u8>)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    extern "system" fn clear(mask: u32) {
+        CALLS.with(|calls| calls.borrow_mut().push((mask, Vec::new())));
+    }
+
+    extern "system" fn buffer_data(target: u32, size: isize, data: *const c_void, _usage: u32) {
+        let data = unsafe { std::slice::from_raw_parts(data as *const u8, size as usize) };
+        CALLS.with(|calls| calls.borrow_mut().push((target, data.to_vec())));
+    }
+
+    mock::reset();
+    mock::set_proc_address("glClear", clear as *const c_void);
+    mock::set_proc_address("glBufferData", buffer_data as *const c_void);
+
+    let path = std::env::temp_dir().join(format!("raw-gl-context-{}.rglt", std::process::id()));
+    let mut config = config();
+    config.record_trace = Some(path.clone());
+    let context = unsafe { GlContext::create(&Window, config).unwrap() };
+    let version = context.actual_config().version;
+    let addr = context.get_proc_address("glClear");
+    assert_ne!(addr, clear as *const c_void);
+
+    unsafe {
+        context.make_current();
+        let traced_clear = std::mem::transmute::<*const c_void, extern "system" fn(u32)>(addr);
+        let traced_buffer_data = std::mem::transmute::<
+            *const c_void,
+            extern "system" fn(u32, isize, *const c_void, u32),
+        >(context.get_proc_address("glBufferData"));
+        traced_clear(0x4000);
+        traced_buffer_data(0x8892, 4, [1u8, 2, 3, 4].as_ptr() as *const c_void, 0x88E4);
+        context.swap_buffers();
+        context.make_not_current();
+    }
+    drop(context);
+
+    let recorded = CALLS.with(|calls| calls.take());
+    assert_eq!(recorded, [(0x4000, vec![]), (0x8892, vec![1, 2, 3, 4])]);
+
+    let trace = Trace::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(trace.version(), version);
+    assert_eq!(trace.frames(), 1);
+
+    let context = create();
+    let mut frames = Vec::new();
+    unsafe {
+        context.make_current();
+        trace.replay(&context, |frame| frames.push(frame)).unwrap();
+        context.make_not_current();
+    }
+
+    assert_eq!(frames, [0]);
+    assert_eq!(CALLS.with(|calls| calls.take()), recorded);
+}
+
+#[cfg(feature = "gl-trace")]
+#[test]
+fn trace_flushed_on_drop() {
+    use std::ffi::c_void;
+
+    use raw_gl_context::replay::Trace;
+
+    extern "system" fn flush() {}
+
+    mock::reset();
+    mock::set_proc_address("glFlush", flush as *const c_void);
+
+    let path = std::env::temp_dir().join(format!("raw-gl-drop-{}.rglt", std::process::id()));
+    let mut config = config();
+    config.record_trace = Some(path.clone());
+
+    let context = unsafe { GlContext::create(&Window, config).unwrap() };
+    let addr = context.get_proc_address("glFlush");
+    unsafe {
+        context.make_current();
+        std::mem::transmute::<*const c_void, extern "system" fn()>(addr)();
+        context.swap_buffers();
+        context.swap_buffers();
+    }
+    drop(context);
+
+    let trace = Trace::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(trace.frames(), 2);
+}
+
+#[test]
+fn creation_restores_current_context() {
+    mock::reset();
+
+    let first = create();
+    unsafe {
+        first.make_current();
+    }
+
+    let second = create();
+    assert!(first.is_current());
+    assert!(!second.is_current());
+
+    let borrowed = unsafe { GlContext::from_raw(second.raw_handles(), Ownership::Borrowed) };
+    assert!(first.is_current());
+    drop(borrowed);
+
+    unsafe {
+        first.make_not_current();
+    }
+    let _third = create();
+    assert!(!first.is_current());
+    assert!(!second.is_current());
+}
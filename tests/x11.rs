@@ -0,0 +1,639 @@
+//! Creates contexts across a matrix of configurations and checks them against the state of
+//! the context. Meant to run under Xvfb with Mesa's llvmpipe (`LIBGL_ALWAYS_SOFTWARE=1`). The
+//! tests need an X server, so they're ignored by default and run with
+//! `xvfb-run cargo test --test x11 -- --include-ignored`.
+
+#![cfg(target_os = "linux")]
+
+use std::ffi::CStr;
+use std::ops::Deref;
+use std::os::raw::{c_int, c_uint, c_ulong};
+
+use raw_gl_context::mesa::Renderer;
+use raw_gl_context::{Backend, GlConfig, GlContext, GlError, Matching, Profile, RawGlHandles};
+use raw_window_handle::unix::XlibHandle;
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use x11::{glx, xlib};
+
+// See https://www.khronos.org/registry/OpenGL/extensions/ARB/ARB_framebuffer_sRGB.txt
+
+const GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB: c_int = 0x20B2;
+
+// See https://www.khronos.org/registry/OpenGL/extensions/EXT/EXT_swap_control.txt
+
+const GLX_SWAP_INTERVAL_EXT: c_int = 0x20F1;
+
+struct Display(*mut xlib::Display);
+
+impl Display {
+    fn open() -> Display {
+        let display = unsafe { xlib::XOpenDisplay(std::ptr::null()) };
+        assert!(!display.is_null(), "no X display available");
+
+        Display(display)
+    }
+}
+
+impl Drop for Display {
+    fn drop(&mut self) {
+        unsafe {
+            xlib::XCloseDisplay(self.0);
+        }
+    }
+}
+
+struct Window<'a> {
+    display: &'a Display,
+    window: c_ulong,
+}
+
+impl<'a> Window<'a> {
+    fn new(display: &'a Display) -> Window<'a> {
+        unsafe {
+            let root = xlib::XDefaultRootWindow(display.0);
+            let window = xlib::XCreateSimpleWindow(display.0, root, 0, 0, 64, 64, 0, 0, 0);
+            xlib::XMapWindow(display.0, window);
+            xlib::XSync(display.0, xlib::False);
+
+            Window { display, window }
+        }
+    }
+}
+
+unsafe impl HasRawWindowHandle for Window<'_> {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        RawWindowHandle::Xlib(XlibHandle {
+            window: self.window,
+            display: self.display.0 as *mut _,
+            ..XlibHandle::empty()
+        })
+    }
+}
+
+impl Drop for Window<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            xlib::XDestroyWindow(self.display.0, self.window);
+        }
+    }
+}
+
+/// A context and the window it was created for. The context is dropped first.
+struct TestContext<'a> {
+    context: GlContext,
+    _window: Window<'a>,
+}
+
+impl Deref for TestContext<'_> {
+    type Target = GlContext;
+
+    fn deref(&self) -> &GlContext {
+        &self.context
+    }
+}
+
+fn create<'a>(display: &'a Display, config: &GlConfig) -> Result<TestContext<'a>, GlError> {
+    let window = Window::new(display);
+    let context = unsafe { GlContext::create(&window, config.clone())? };
+
+    Ok(TestContext {
+        context,
+        _window: window,
+    })
+}
+
+fn fb_config_attrib(context: &GlContext, attrib: c_int) -> c_int {
+    match context.raw_handles() {
+        RawGlHandles::Glx {
+            display, fb_config, ..
+        } => {
+            let mut value = 0;
+            unsafe {
+                glx::glXGetFBConfigAttrib(
+                    display as *mut _,
+                    fb_config as *mut _,
+                    attrib,
+                    &mut value,
+                );
+            }
+            value
+        }
+        _ => unreachable!(),
+    }
+}
+
+unsafe fn gl_version() -> (u8, u8) {
+    let version = gl::GetString(gl::VERSION);
+    let version = CStr::from_ptr(version as *const _).to_string_lossy();
+    let mut numbers = version.split(|c: char| !c.is_ascii_digit());
+    let major = numbers.next().unwrap().parse().unwrap();
+    let minor = numbers.next().unwrap().parse().unwrap();
+    (major, minor)
+}
+
+#[test]
+#[ignore = "needs an X server"]
+fn versions_and_profiles() {
+    let display = Display::open();
+
+    let matrix = [
+        ((2, 1), Profile::Compatibility),
+        ((3, 0), Profile::Compatibility),
+        ((3, 2), Profile::Core),
+        ((3, 3), Profile::Core),
+        ((4, 1), Profile::Core),
+        ((4, 5), Profile::Core),
+    ];
+
+    for &(version, profile) in &matrix {
+        let mut config = GlConfig::default();
+        config.version = version;
+        config.profile = profile;
+        let context = create(&display, &config)
+            .unwrap_or_else(|err| panic!("{:?} {:?}: {:?}", version, profile, err));
+
+        unsafe {
+            context.make_current();
+            gl::load_with(|symbol| context.get_proc_address(symbol));
+
+            let actual = gl_version();
+            assert!(
+                actual >= version,
+                "requested {:?}, got {:?}",
+                version,
+                actual
+            );
+            assert_eq!(context.actual_config().version, actual);
+
+            if version >= (3, 2) {
+                let mut mask = 0;
+                gl::GetIntegerv(gl::CONTEXT_PROFILE_MASK, &mut mask);
+                let expected = match profile {
+                    Profile::Core => gl::CONTEXT_CORE_PROFILE_BIT,
+                    Profile::Compatibility => gl::CONTEXT_COMPATIBILITY_PROFILE_BIT,
+                };
+                assert_ne!(mask as c_uint & expected, 0, "{:?} {:?}", version, profile);
+            }
+            assert_eq!(context.actual_config().profile, profile);
+
+            context.make_not_current();
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs an X server"]
+fn framebuffer_attributes() {
+    let display = Display::open();
+
+    for &srgb in &[false, true] {
+        for &samples in &[None, Some(2), Some(4)] {
+            for &double_buffer in &[false, true] {
+                let mut config = GlConfig::default();
+                config.srgb = srgb;
+                config.samples = samples;
+                config.double_buffer = double_buffer;
+                let context = create(&display, &config).unwrap_or_else(|err| {
+                    panic!(
+                        "srgb {} samples {:?} double buffer {}: {:?}",
+                        srgb, samples, double_buffer, err
+                    )
+                });
+                let actual = context.actual_config();
+
+                assert_eq!(
+                    fb_config_attrib(&context, glx::GLX_DOUBLEBUFFER) != 0,
+                    double_buffer
+                );
+                assert_eq!(actual.double_buffer, double_buffer);
+
+                let sample_buffers = fb_config_attrib(&context, glx::GLX_SAMPLE_BUFFERS);
+                let fb_samples = fb_config_attrib(&context, glx::GLX_SAMPLES);
+                match samples {
+                    Some(samples) => {
+                        assert!(sample_buffers > 0);
+                        assert!(fb_samples >= samples as c_int);
+                        assert_eq!(actual.samples, Some(fb_samples as u8));
+                    }
+                    None => assert_eq!(actual.samples.is_some(), sample_buffers > 0),
+                }
+
+                let srgb_capable = fb_config_attrib(&context, GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB);
+                if srgb {
+                    assert_ne!(srgb_capable, 0);
+                }
+                assert_eq!(actual.srgb, srgb_capable != 0);
+
+                assert!(actual.red_bits >= 8);
+                assert!(actual.depth_bits >= 24);
+                assert_eq!(
+                    actual.depth_bits as c_int,
+                    fb_config_attrib(&context, glx::GLX_DEPTH_SIZE)
+                );
+            }
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs an X server"]
+fn vsync() {
+    let display = Display::open();
+
+    for &vsync in &[false, true] {
+        let mut config = GlConfig::default();
+        config.vsync = vsync;
+        let context = create(&display, &config).unwrap();
+
+        if !context.has_extension("GLX_EXT_swap_control") {
+            continue;
+        }
+
+        if let RawGlHandles::Glx {
+            display, drawable, ..
+        } = context.raw_handles()
+        {
+            let mut interval = 0;
+            unsafe {
+                glx::glXQueryDrawable(
+                    display as *mut _,
+                    drawable,
+                    GLX_SWAP_INTERVAL_EXT,
+                    &mut interval,
+                );
+            }
+            assert_eq!(interval != 0, vsync);
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs an X server"]
+fn exact_matching() {
+    let display = Display::open();
+
+    let mut config = GlConfig::default();
+    config.red_bits = 7;
+    config.matching = Matching::Exact;
+    match create(&display, &config) {
+        Err(GlError::NoExactMatch(alternatives)) => assert!(!alternatives.is_empty()),
+        Err(err) => panic!("{:?}", err),
+        Ok(_) => panic!("no 7-bit red config should exist"),
+    }
+
+    let mut config = GlConfig::default();
+    config.matching = Matching::Exact;
+    config.samples = None;
+    let context = create(&display, &config).unwrap();
+    assert_eq!(context.actual_config().red_bits, config.red_bits);
+}
+
+#[test]
+#[ignore = "needs an X server"]
+fn renderer_info() {
+    let display = Display::open();
+
+    let context = create(&display, &GlConfig::default()).unwrap();
+    let info = context.info();
+
+    assert!(!info.vendor.is_empty());
+    assert!(!info.renderer.is_empty());
+    assert!(info
+        .version
+        .starts_with(&format!("{}.", context.actual_config().version.0)));
+    assert!(!info.shading_language_version.is_empty());
+    assert_eq!(info.backend, Backend::Glx);
+    assert_eq!(info.direct, context.is_direct());
+
+    let glx = info.glx.as_ref().unwrap();
+    assert!(!glx.client_vendor.is_empty());
+    assert!(!glx.server_version.is_empty());
+
+    if std::env::var_os("LIBGL_ALWAYS_SOFTWARE").is_some() {
+        assert!(info.software, "{}", info.renderer);
+    }
+}
+
+#[test]
+#[ignore = "needs an X server"]
+fn mesa_query_renderer() {
+    let display = Display::open();
+
+    let screen = unsafe { xlib::XDefaultScreen(display.0) };
+    let renderer = match unsafe { Renderer::query(display.0 as *mut _, screen) } {
+        Some(renderer) => renderer,
+        None => return,
+    };
+
+    let version = renderer.max_version(Profile::Core).unwrap();
+    let mut config = GlConfig::default();
+    config.version = version;
+    config.profile = Profile::Core;
+    let context = create(&display, &config).unwrap();
+    assert_eq!(context.actual_config().version, version);
+    assert_eq!(renderer.accelerated, !context.info().software);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[ignore = "needs an X server"]
+fn injected_faults() {
+    use raw_gl_context::fault::{self, Fault};
+
+    let display = Display::open();
+
+    for &fault in &[
+        Fault::ChooseFbConfig,
+        Fault::CreateContextAttribsLookup,
+        Fault::CreateContext,
+        Fault::MakeCurrent,
+    ] {
+        fault::inject(fault);
+        let result = create(&display, &GlConfig::default());
+        fault::clear();
+
+        assert!(
+            matches!(result, Err(GlError::CreationFailed)),
+            "{:?}: {:?}",
+            fault,
+            result.err()
+        );
+    }
+
+    // Without glXSwapIntervalEXT the context is still created, just without setting vsync.
+    fault::inject(Fault::SwapIntervalLookup);
+    let result = create(&display, &GlConfig::default());
+    fault::clear();
+    assert!(result.is_ok());
+
+    // Releasing the context isn't faulted.
+    let context = create(&display, &GlConfig::default()).unwrap();
+    unsafe {
+        context.make_current();
+        fault::inject(Fault::MakeCurrent);
+        context.make_not_current();
+        fault::clear();
+        assert!(glx::glXGetCurrentContext().is_null());
+    }
+}
+
+/// Records a draw with an odd-width, tightly packed texture, replays it with raw-gl-replay and
+/// checks the frame it wrote.
+#[cfg(all(feature = "gl-trace", feature = "testing"))]
+#[test]
+#[ignore = "needs an X server"]
+fn trace_replay() {
+    use std::ffi::c_void;
+    use std::process::Command;
+
+    let display = Display::open();
+
+    let dir = std::env::temp_dir().join(format!("raw-gl-trace-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("draw.rglt");
+
+    let mut config = GlConfig::default();
+    config.version = (2, 1);
+    config.profile = Profile::Compatibility;
+    config.record_trace = Some(path.clone());
+    let context = create(&display, &config).unwrap();
+    unsafe {
+        context.make_current();
+        let gl = context.load_functions(&[]).unwrap();
+
+        let texels: [u8; 9] = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let mut texture = 0;
+        gl.GenTextures(1, &mut texture);
+        gl.BindTexture(gl::TEXTURE_2D, texture);
+        gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl.TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGB as i32,
+            3,
+            1,
+            0,
+            gl::RGB,
+            gl::UNSIGNED_BYTE,
+            texels.as_ptr() as *const c_void,
+        );
+        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        gl.Enable(gl::TEXTURE_2D);
+
+        gl.Clear(gl::COLOR_BUFFER_BIT);
+        gl.Begin(gl::QUADS);
+        for &(x, y) in &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            gl.TexCoord2f(x, y);
+            gl.Vertex2f(x * 2.0 - 1.0, y * 2.0 - 1.0);
+        }
+        gl.End();
+        context.swap_buffers();
+        context.make_not_current();
+    }
+    drop(context);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_raw-gl-replay"))
+        .arg(&path)
+        .arg("--out")
+        .arg(&dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let frame = image::open(dir.join("frame_0000.png")).unwrap().to_rgba8();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(frame.dimensions(), (64, 64));
+    for &(x, color) in &[(10, [255, 0, 0]), (32, [0, 255, 0]), (54, [0, 0, 255])] {
+        let pixel = frame.get_pixel(x, 32).0;
+        assert_eq!(pixel[..3], color, "pixel at x = {}", x);
+    }
+}
+
+#[test]
+#[ignore = "needs an X server"]
+fn read_pixels_restores_state() {
+    use raw_gl_context::{PixelFormat, ReadBuffer, Region};
+
+    let display = Display::open();
+
+    let context = create(&display, &GlConfig::default()).unwrap();
+    unsafe {
+        context.make_current();
+        gl::load_with(|symbol| context.get_proc_address(symbol));
+
+        gl::ReadBuffer(gl::FRONT);
+        gl::ClearColor(1.0, 0.0, 0.0, 1.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+
+        let (mut framebuffer, mut renderbuffer, mut buffer) = (0, 0, 0);
+        gl::GenFramebuffers(1, &mut framebuffer);
+        gl::GenRenderbuffers(1, &mut renderbuffer);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, 8, 8);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+        gl::FramebufferRenderbuffer(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::RENDERBUFFER,
+            renderbuffer,
+        );
+        gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+        gl::GenBuffers(1, &mut buffer);
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, buffer);
+        gl::BufferData(
+            gl::PIXEL_PACK_BUFFER,
+            1024,
+            std::ptr::null(),
+            gl::STREAM_READ,
+        );
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 8);
+        gl::PixelStorei(gl::PACK_ROW_LENGTH, 16);
+        gl::PixelStorei(gl::PACK_SKIP_ROWS, 2);
+
+        // 5 pixels wide, so that each row of RGB is 15 bytes and alignment would pad it.
+        let image = context
+            .read_pixels_from(ReadBuffer::Back, Region::full(5, 3), PixelFormat::Rgb8)
+            .unwrap();
+        assert_eq!(image.data.len(), 5 * 3 * 3);
+        assert!(image.data.chunks(3).all(|pixel| pixel == [255, 0, 0]));
+
+        let integer = |name| {
+            let mut value = 0;
+            gl::GetIntegerv(name, &mut value);
+            value
+        };
+        assert_eq!(integer(gl::READ_FRAMEBUFFER_BINDING), framebuffer as i32);
+        assert_eq!(integer(gl::READ_BUFFER), gl::COLOR_ATTACHMENT0 as i32);
+        assert_eq!(integer(gl::PIXEL_PACK_BUFFER_BINDING), buffer as i32);
+        assert_eq!(integer(gl::PACK_ALIGNMENT), 8);
+        assert_eq!(integer(gl::PACK_ROW_LENGTH), 16);
+        assert_eq!(integer(gl::PACK_SKIP_ROWS), 2);
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        assert_eq!(integer(gl::READ_BUFFER), gl::FRONT as i32);
+        assert_eq!(gl::GetError(), gl::NO_ERROR);
+
+        context.make_not_current();
+    }
+}
+
+#[test]
+#[ignore = "needs an X server"]
+fn creation_restores_current_context() {
+    let display = Display::open();
+
+    let first = create(&display, &GlConfig::default()).unwrap();
+    unsafe {
+        first.make_current();
+    }
+    let second = create(&display, &GlConfig::default()).unwrap();
+    assert!(first.is_current());
+    assert!(!second.is_current());
+
+    unsafe {
+        first.make_not_current();
+    }
+    let _third = create(&display, &GlConfig::default()).unwrap();
+    assert!(unsafe { glx::glXGetCurrentContext() }.is_null());
+}
+
+/// Starts an Xvfb with two screens and creates a context for a window on the second, which
+/// must get a visual of that screen rather than of the default one.
+#[test]
+#[ignore = "needs Xvfb"]
+fn second_screen() {
+    use std::ffi::CString;
+    use std::process::{Child, Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    /// Killed on drop, even if the test fails.
+    struct Xvfb(Child);
+
+    impl Drop for Xvfb {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    let number = (120..200)
+        .find(|n| !std::path::Path::new(&format!("/tmp/.X{}-lock", n)).exists())
+        .expect("no free display number");
+    let name = format!(":{}", number);
+    let _xvfb = Xvfb(
+        Command::new("Xvfb")
+            .args([
+                &name,
+                "-screen",
+                "0",
+                "640x480x24",
+                "-screen",
+                "1",
+                "320x240x24",
+            ])
+            .args(["-nolisten", "tcp"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Xvfb isn't installed"),
+    );
+
+    let c_name = CString::new(name).unwrap();
+    let start = Instant::now();
+    let display = loop {
+        let display = unsafe { xlib::XOpenDisplay(c_name.as_ptr()) };
+        if !display.is_null() {
+            break Display(display);
+        }
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "Xvfb didn't start"
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    };
+
+    unsafe {
+        assert_eq!(xlib::XScreenCount(display.0), 2);
+        let root = xlib::XRootWindow(display.0, 1);
+        let window = xlib::XCreateSimpleWindow(display.0, root, 0, 0, 64, 64, 0, 0, 0);
+        xlib::XMapWindow(display.0, window);
+        xlib::XSync(display.0, xlib::False);
+        let window = Window {
+            display: &display,
+            window,
+        };
+
+        for &screen in &[None, Some(1)] {
+            let mut config = GlConfig::default();
+            config.screen = screen;
+            let context = GlContext::create(&window, config).unwrap();
+            assert_eq!(fb_config_attrib(&context, glx::GLX_SCREEN), 1);
+
+            let visual_id = match context.raw_handles() {
+                RawGlHandles::Glx { visual_id, .. } => visual_id,
+                _ => unreachable!(),
+            };
+            let mut template: xlib::XVisualInfo = std::mem::zeroed();
+            template.visualid = visual_id;
+            template.screen = 1;
+            let mut count = 0;
+            let info = xlib::XGetVisualInfo(
+                display.0,
+                xlib::VisualIDMask | xlib::VisualScreenMask,
+                &mut template,
+                &mut count,
+            );
+            assert_eq!(count, 1, "visual {:#x} isn't on screen 1", visual_id);
+            xlib::XFree(info as *mut _);
+
+            context.make_current();
+            context.swap_buffers();
+            context.make_not_current();
+        }
+    }
+}